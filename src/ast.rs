@@ -16,20 +16,91 @@ pub enum Expr {
     Infix(Infix, Box<Expr>, Box<Expr>),
 }
 
+impl Expr {
+    pub fn precedence(&self) -> Precedence {
+        match self {
+            Expr::Literal(_) | Expr::Prefix(_, _) => Precedence::Prefix,
+            Expr::Infix(i, _, _) => i.precedence(),
+        }
+    }
+
+    /// Whether evaluating the expression can never raise a runtime error, so
+    /// that dropping it or moving it around does not change the program's
+    /// behaviour.
+    ///
+    /// Negation and every infix operation can overflow, and division can also
+    /// divide by zero.
+    pub fn is_pure(&self) -> bool {
+        match self {
+            Expr::Literal(_) => true,
+            Expr::Prefix(Prefix::Minus, _) => false,
+            Expr::Prefix(_, x) => x.is_pure(),
+            Expr::Infix(_, _, _) => false,
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Literal(l) => write!(f, "{}", l),
+            Expr::Prefix(p, x) => {
+                write!(f, "{}", p)?;
+                // `-1` is read as a negative literal, so negating a literal
+                // needs parentheses to stay a prefix operation.
+                let negates_literal = match (p, x.as_ref()) {
+                    (Prefix::Minus, Expr::Literal(Literal::Int(i))) => *i >= 0,
+                    _ => false,
+                };
+                write_operand(f, x, negates_literal || x.precedence() < Precedence::Prefix)
+            }
+            Expr::Infix(i, x, y) => {
+                let precedence = i.precedence();
+                // Infix operators are left-associative, so only the right
+                // operand needs parentheses at equal precedence.
+                write_operand(f, x, x.precedence() < precedence)?;
+                write!(f, " {} ", i)?;
+                write_operand(f, y, y.precedence() <= precedence)
+            }
+        }
+    }
+}
+
+fn write_operand(f: &mut fmt::Formatter, ex: &Expr, grouped: bool) -> fmt::Result {
+    match grouped {
+        true => write!(f, "({})", ex),
+        false => write!(f, "{}", ex),
+    }
+}
+
 #[derive(PartialEq, Clone, Debug)]
 pub enum Literal {
     Int(i64),
 }
 
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Literal::Int(i) => write!(f, "{}", i),
+        }
+    }
+}
+
 #[derive(PartialEq, Clone, Debug)]
 pub enum Prefix {
+    Plus,
     Minus,
+    BitNot,
+    Not,
 }
 
 impl fmt::Display for Prefix {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
+            Prefix::Plus => write!(f, "+"),
             Prefix::Minus => write!(f, "-"),
+            Prefix::BitNot => write!(f, "~"),
+            Prefix::Not => write!(f, "!"),
         }
     }
 }
@@ -42,6 +113,15 @@ pub enum Infix {
     Divide,
 }
 
+impl Infix {
+    pub fn precedence(&self) -> Precedence {
+        match *self {
+            Infix::Plus | Infix::Minus => Precedence::Sum,
+            Infix::Multiply | Infix::Divide => Precedence::Product,
+        }
+    }
+}
+
 impl fmt::Display for Infix {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -60,3 +140,306 @@ pub enum Precedence {
     Product, // * /
     Prefix,
 }
+
+/// Read-only traversal over a program. Every method defaults to walking into
+/// the children, so an implementation only overrides the nodes it cares about
+/// and calls the matching `walk_*` function to keep descending.
+pub trait Visitor {
+    fn visit_program(&mut self, program: &Program) {
+        walk_program(self, program)
+    }
+    fn visit_statement(&mut self, s: &Statement) {
+        walk_statement(self, s)
+    }
+    fn visit_expr(&mut self, ex: &Expr) {
+        walk_expr(self, ex)
+    }
+    fn visit_literal(&mut self, _l: &Literal) {}
+    fn visit_prefix(&mut self, _p: &Prefix) {}
+    fn visit_infix(&mut self, _i: &Infix) {}
+}
+
+pub fn walk_program<V: Visitor + ?Sized>(v: &mut V, program: &Program) {
+    for s in program {
+        v.visit_statement(s);
+    }
+}
+
+pub fn walk_statement<V: Visitor + ?Sized>(v: &mut V, s: &Statement) {
+    match s {
+        Statement::Expression(ex) => v.visit_expr(ex),
+    }
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(v: &mut V, ex: &Expr) {
+    match ex {
+        Expr::Literal(l) => v.visit_literal(l),
+        Expr::Prefix(p, x) => {
+            v.visit_prefix(p);
+            v.visit_expr(x);
+        }
+        Expr::Infix(i, x, y) => {
+            v.visit_infix(i);
+            v.visit_expr(x);
+            v.visit_expr(y);
+        }
+    }
+}
+
+/// In-place traversal over a program, the mutable counterpart of `Visitor`.
+pub trait MutVisitor {
+    fn visit_program_mut(&mut self, program: &mut Program) {
+        walk_program_mut(self, program)
+    }
+    fn visit_statement_mut(&mut self, s: &mut Statement) {
+        walk_statement_mut(self, s)
+    }
+    fn visit_expr_mut(&mut self, ex: &mut Expr) {
+        walk_expr_mut(self, ex)
+    }
+    fn visit_literal_mut(&mut self, _l: &mut Literal) {}
+    fn visit_prefix_mut(&mut self, _p: &mut Prefix) {}
+    fn visit_infix_mut(&mut self, _i: &mut Infix) {}
+}
+
+pub fn walk_program_mut<V: MutVisitor + ?Sized>(v: &mut V, program: &mut Program) {
+    for s in program {
+        v.visit_statement_mut(s);
+    }
+}
+
+pub fn walk_statement_mut<V: MutVisitor + ?Sized>(v: &mut V, s: &mut Statement) {
+    match s {
+        Statement::Expression(ex) => v.visit_expr_mut(ex),
+    }
+}
+
+pub fn walk_expr_mut<V: MutVisitor + ?Sized>(v: &mut V, ex: &mut Expr) {
+    match ex {
+        Expr::Literal(l) => v.visit_literal_mut(l),
+        Expr::Prefix(p, x) => {
+            v.visit_prefix_mut(p);
+            v.visit_expr_mut(x);
+        }
+        Expr::Infix(i, x, y) => {
+            v.visit_infix_mut(i);
+            v.visit_expr_mut(x);
+            v.visit_expr_mut(y);
+        }
+    }
+}
+
+/// Owning traversal that rebuilds a program bottom-up. Overriding `fold_expr`
+/// and calling `walk_fold_expr` first gives a rewrite that sees already
+/// folded children.
+pub trait Fold {
+    fn fold_program(&mut self, program: Program) -> Program {
+        walk_fold_program(self, program)
+    }
+    fn fold_statement(&mut self, s: Statement) -> Statement {
+        walk_fold_statement(self, s)
+    }
+    fn fold_expr(&mut self, ex: Expr) -> Expr {
+        walk_fold_expr(self, ex)
+    }
+    fn fold_literal(&mut self, l: Literal) -> Literal {
+        l
+    }
+    fn fold_prefix(&mut self, p: Prefix) -> Prefix {
+        p
+    }
+    fn fold_infix(&mut self, i: Infix) -> Infix {
+        i
+    }
+}
+
+pub fn walk_fold_program<F: Fold + ?Sized>(f: &mut F, program: Program) -> Program {
+    program.into_iter().map(|s| f.fold_statement(s)).collect()
+}
+
+pub fn walk_fold_statement<F: Fold + ?Sized>(f: &mut F, s: Statement) -> Statement {
+    match s {
+        Statement::Expression(ex) => Statement::Expression(f.fold_expr(ex)),
+    }
+}
+
+pub fn walk_fold_expr<F: Fold + ?Sized>(f: &mut F, ex: Expr) -> Expr {
+    match ex {
+        Expr::Literal(l) => Expr::Literal(f.fold_literal(l)),
+        Expr::Prefix(p, x) => Expr::Prefix(f.fold_prefix(p), Box::new(f.fold_expr(*x))),
+        Expr::Infix(i, x, y) => {
+            let i = f.fold_infix(i);
+            let x = f.fold_expr(*x);
+            let y = f.fold_expr(*y);
+            Expr::Infix(i, Box::new(x), Box::new(y))
+        }
+    }
+}
+
+/// Draws `program` as an indented tree, one node per line.
+pub fn dump(program: &Program) -> String {
+    let mut tree = Tree {
+        depth: 0,
+        dump: String::new(),
+    };
+    tree.visit_program(program);
+    tree.dump
+}
+
+struct Tree {
+    depth: usize,
+    dump: String,
+}
+
+impl Tree {
+    fn node(&mut self, label: String) {
+        self.dump.push_str(&"  ".repeat(self.depth));
+        self.dump.push_str(&label);
+        self.dump.push('\n');
+    }
+
+    fn children<F: FnOnce(&mut Self)>(&mut self, walk: F) {
+        self.depth += 1;
+        walk(self);
+        self.depth -= 1;
+    }
+}
+
+impl Visitor for Tree {
+    fn visit_program(&mut self, program: &Program) {
+        self.node("Program".to_string());
+        self.children(|t| walk_program(t, program));
+    }
+
+    fn visit_statement(&mut self, s: &Statement) {
+        match s {
+            Statement::Expression(_) => self.node("Expression".to_string()),
+        }
+        self.children(|t| walk_statement(t, s));
+    }
+
+    fn visit_expr(&mut self, ex: &Expr) {
+        match ex {
+            Expr::Literal(l) => self.node(format!("Literal {}", l)),
+            Expr::Prefix(p, _) => self.node(format!("Prefix {}", p)),
+            Expr::Infix(i, _, _) => self.node(format!("Infix {}", i)),
+        }
+        self.children(|t| walk_expr(t, ex));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Program {
+        Parser::new(Lexer::new(input)).parse().unwrap()
+    }
+
+    struct Depth {
+        current: usize,
+        max: usize,
+    }
+
+    impl Visitor for Depth {
+        fn visit_expr(&mut self, ex: &Expr) {
+            self.current += 1;
+            self.max = self.max.max(self.current);
+            walk_expr(self, ex);
+            self.current -= 1;
+        }
+    }
+
+    struct Literals(Vec<i64>);
+
+    impl Visitor for Literals {
+        fn visit_literal(&mut self, l: &Literal) {
+            let Literal::Int(i) = *l;
+            self.0.push(i);
+        }
+    }
+
+    #[test]
+    fn test_visitor() {
+        let tests = vec![
+            ("1", 1, vec![1]),
+            ("-(1)", 2, vec![1]),
+            ("-1", 1, vec![-1]),
+            ("1+2*3", 3, vec![1, 2, 3]),
+            ("(1+2)*-(3-4) 5", 4, vec![1, 2, 3, 4, 5]),
+        ];
+
+        for (input, depth, literals) in tests {
+            let program = parse(input);
+
+            let mut d = Depth { current: 0, max: 0 };
+            d.visit_program(&program);
+            assert_eq!(depth, d.max);
+
+            let mut l = Literals(vec![]);
+            l.visit_program(&program);
+            assert_eq!(literals, l.0);
+        }
+    }
+
+    struct SwapOperands;
+
+    impl MutVisitor for SwapOperands {
+        fn visit_expr_mut(&mut self, ex: &mut Expr) {
+            if let Expr::Infix(_, x, y) = ex {
+                std::mem::swap(x, y);
+            }
+            walk_expr_mut(self, ex);
+        }
+    }
+
+    #[test]
+    fn test_mut_visitor() {
+        let tests = vec![("1", "1"), ("1-2", "2-1"), ("(1-2)/-(3*4)", "-(4*3)/(2-1)")];
+
+        for (input, want) in tests {
+            let mut program = parse(input);
+            SwapOperands.visit_program_mut(&mut program);
+            assert_eq!(parse(want), program);
+        }
+    }
+
+    struct RemoveUnaryPlus;
+
+    impl Fold for RemoveUnaryPlus {
+        fn fold_expr(&mut self, ex: Expr) -> Expr {
+            match walk_fold_expr(self, ex) {
+                Expr::Prefix(Prefix::Plus, x) => *x,
+                ex => ex,
+            }
+        }
+    }
+
+    #[test]
+    fn test_fold() {
+        let tests = vec![("+1", "1"), ("++-+1", "-(1)"), ("+(1-+2)*+3", "(1-2)*3")];
+
+        for (input, want) in tests {
+            assert_eq!(parse(want), RemoveUnaryPlus.fold_program(parse(input)));
+        }
+    }
+
+    #[test]
+    fn test_dump() {
+        let want = r#"Program
+  Expression
+    Infix *
+      Infix +
+        Literal 1
+        Literal 2
+      Prefix -
+        Literal 3
+  Expression
+    Literal -4
+"#;
+
+        assert_eq!(want, dump(&parse("(1+2)*-(3) (-4)")));
+    }
+}
@@ -0,0 +1,427 @@
+use crate::ast::*;
+use crate::error::BytecodeError;
+use std::convert::TryInto;
+
+/// One-byte opcodes. Only `Push` takes an operand: a little-endian `u32`
+/// index into the constant pool.
+#[derive(PartialEq, Clone, Copy, Debug)]
+#[repr(u8)]
+pub enum Opcode {
+    Push = 0x01,
+    Add = 0x02,
+    Sub = 0x03,
+    Mul = 0x04,
+    Div = 0x05,
+    Neg = 0x06,
+    BitNot = 0x07,
+    Not = 0x08,
+    Return = 0x09,
+}
+
+impl Opcode {
+    pub fn from_byte(byte: u8) -> Option<Opcode> {
+        match byte {
+            0x01 => Some(Opcode::Push),
+            0x02 => Some(Opcode::Add),
+            0x03 => Some(Opcode::Sub),
+            0x04 => Some(Opcode::Mul),
+            0x05 => Some(Opcode::Div),
+            0x06 => Some(Opcode::Neg),
+            0x07 => Some(Opcode::BitNot),
+            0x08 => Some(Opcode::Not),
+            0x09 => Some(Opcode::Return),
+            _ => None,
+        }
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match *self {
+            Opcode::Push => "push",
+            Opcode::Add => "add",
+            Opcode::Sub => "sub",
+            Opcode::Mul => "mul",
+            Opcode::Div => "div",
+            Opcode::Neg => "neg",
+            Opcode::BitNot => "bitnot",
+            Opcode::Not => "not",
+            Opcode::Return => "return",
+        }
+    }
+
+    /// Bytes taken by the operand following the opcode.
+    pub fn operand_len(&self) -> usize {
+        match *self {
+            Opcode::Push => 4,
+            _ => 0,
+        }
+    }
+}
+
+/// A compiled program: code for a stack machine and the constants it
+/// pushes.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct Chunk {
+    pub constants: Vec<i64>,
+    pub code: Vec<u8>,
+}
+
+impl Chunk {
+    pub fn push(&mut self, n: i64) {
+        let index = match self.constants.iter().position(|c| *c == n) {
+            Some(index) => index,
+            None => {
+                self.constants.push(n);
+                self.constants.len() - 1
+            }
+        };
+        self.code.push(Opcode::Push as u8);
+        self.code.extend(&(index as u32).to_le_bytes());
+    }
+
+    pub fn emit(&mut self, op: Opcode) {
+        self.code.push(op as u8);
+    }
+
+    /// The operand of the `Push` at `offset`.
+    pub fn read_index(&self, offset: usize) -> usize {
+        u32::from_le_bytes(self.code[offset + 1..offset + 5].try_into().unwrap()) as usize
+    }
+
+    /// Whether the VM can run the chunk: every opcode is known and complete,
+    /// every constant exists, the stack never underflows, and the code ends
+    /// with the only `Return`, leaving at most one value.
+    pub fn validate(&self) -> Result<(), BytecodeError> {
+        let mut pc = 0;
+        let mut depth = 0;
+
+        while let Some(op) = self.code.get(pc).map(|b| Opcode::from_byte(*b)) {
+            let op = op.ok_or(BytecodeError::InvalidCode)?;
+            if pc + 1 + op.operand_len() > self.code.len() {
+                return Err(BytecodeError::InvalidCode);
+            }
+
+            let (pops, pushes) = match op {
+                Opcode::Push if self.read_index(pc) >= self.constants.len() => {
+                    return Err(BytecodeError::InvalidCode)
+                }
+                Opcode::Push => (0, 1),
+                Opcode::Neg | Opcode::BitNot | Opcode::Not => (1, 1),
+                Opcode::Add | Opcode::Sub | Opcode::Mul | Opcode::Div => (2, 1),
+                Opcode::Return if pc + 1 == self.code.len() && depth <= 1 => return Ok(()),
+                Opcode::Return => return Err(BytecodeError::InvalidCode),
+            };
+            if depth < pops {
+                return Err(BytecodeError::InvalidCode);
+            }
+            depth = depth - pops + pushes;
+            pc += 1 + op.operand_len();
+        }
+
+        Err(BytecodeError::InvalidCode)
+    }
+
+    /// Lists the constant pool and one instruction per line, each prefixed
+    /// with its offset.
+    pub fn disassemble(&self) -> String {
+        let mut s = String::from("constants:\n");
+        for (n, c) in self.constants.iter().enumerate() {
+            s.push_str(&format!("  {}: {}\n", n, c));
+        }
+
+        s.push_str("code:\n");
+        let mut pc = 0;
+        while pc < self.code.len() {
+            match Opcode::from_byte(self.code[pc]) {
+                Some(Opcode::Push) => {
+                    let index = self.read_index(pc);
+                    s.push_str(&format!(
+                        "  {:04x} push {} ; {}\n",
+                        pc, index, self.constants[index]
+                    ));
+                }
+                Some(op) => s.push_str(&format!("  {:04x} {}\n", pc, op.mnemonic())),
+                None => s.push_str(&format!("  {:04x} .byte {:#04x}\n", pc, self.code[pc])),
+            }
+            pc += 1 + Opcode::from_byte(self.code[pc]).map_or(0, |op| op.operand_len());
+        }
+
+        s
+    }
+
+    /// Serializes the chunk into a `.cbc` file.
+    ///
+    /// All integers are little-endian:
+    ///
+    /// ```text
+    /// magic      b"CBC\0"
+    /// version    u16
+    /// constants  u32 count, then one i64 each
+    /// code       u32 length, then the bytes
+    /// checksum   u32 CRC-32 of everything before it
+    /// ```
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(&VERSION.to_le_bytes());
+
+        bytes.extend(&(self.constants.len() as u32).to_le_bytes());
+        for c in &self.constants {
+            bytes.extend(&c.to_le_bytes());
+        }
+
+        bytes.extend(&(self.code.len() as u32).to_le_bytes());
+        bytes.extend(&self.code);
+
+        let checksum = crc32(&bytes);
+        bytes.extend(&checksum.to_le_bytes());
+        bytes
+    }
+
+    /// Reads a `.cbc` file written by `encode`, and validates the chunk.
+    pub fn decode(bytes: &[u8]) -> Result<Chunk, BytecodeError> {
+        if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(BytecodeError::BadMagic);
+        }
+        let mut r = Reader {
+            bytes,
+            pos: MAGIC.len(),
+        };
+
+        let version = u16::from_le_bytes(r.take(2)?.try_into().unwrap());
+        if version != VERSION {
+            return Err(BytecodeError::UnsupportedVersion);
+        }
+
+        let count = r.read_u32()? as usize;
+        let mut constants = vec![];
+        for _ in 0..count {
+            constants.push(i64::from_le_bytes(r.take(8)?.try_into().unwrap()));
+        }
+
+        let len = r.read_u32()? as usize;
+        let code = r.take(len)?.to_vec();
+
+        let end = r.pos;
+        if r.read_u32()? != crc32(&bytes[..end]) {
+            return Err(BytecodeError::ChecksumMismatch);
+        }
+        if r.pos != bytes.len() {
+            return Err(BytecodeError::TrailingData);
+        }
+
+        let chunk = Chunk { constants, code };
+        chunk.validate()?;
+        Ok(chunk)
+    }
+}
+
+const MAGIC: &[u8] = b"CBC\0";
+const VERSION: u16 = 1;
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], BytecodeError> {
+        match self.bytes.get(self.pos..self.pos.saturating_add(n)) {
+            Some(s) => {
+                self.pos += n;
+                Ok(s)
+            }
+            None => Err(BytecodeError::Truncated),
+        }
+    }
+
+    fn read_u32(&mut self) -> Result<u32, BytecodeError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+/// CRC-32 as used by zlib and PNG.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for b in bytes {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xedb8_8320,
+                _ => crc >> 1,
+            };
+        }
+    }
+    !crc
+}
+
+/// Compiles the first statement of `program`. An empty program compiles
+/// to a lone `Return`, which the VM reports as having no program.
+pub fn compile(program: &Program) -> Chunk {
+    let mut chunk = Chunk::default();
+    if let Some(Statement::Expression(ex)) = program.first() {
+        compile_expression(ex, &mut chunk);
+    }
+    chunk.emit(Opcode::Return);
+    chunk
+}
+
+fn compile_expression(ex: &Expr, chunk: &mut Chunk) {
+    match ex {
+        Expr::Literal(Literal::Int(i)) => chunk.push(*i),
+        Expr::Prefix(p, x) => {
+            compile_expression(x, chunk);
+            match *p {
+                Prefix::Plus => {}
+                Prefix::Minus => chunk.emit(Opcode::Neg),
+                Prefix::BitNot => chunk.emit(Opcode::BitNot),
+                Prefix::Not => chunk.emit(Opcode::Not),
+            }
+        }
+        Expr::Infix(i, x, y) => {
+            compile_expression(x, chunk);
+            compile_expression(y, chunk);
+            chunk.emit(match *i {
+                Infix::Plus => Opcode::Add,
+                Infix::Minus => Opcode::Sub,
+                Infix::Multiply => Opcode::Mul,
+                Infix::Divide => Opcode::Div,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bytecode::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Program {
+        Parser::new(Lexer::new(input)).parse().unwrap()
+    }
+
+    #[test]
+    fn test_compile() {
+        let tests = vec![
+            ("", vec![], vec![0x09]),
+            ("+7", vec![7], vec![0x01, 0, 0, 0, 0, 0x09]),
+            (
+                "-(2*3)/2",
+                vec![2, 3],
+                vec![
+                    0x01, 0, 0, 0, 0, 0x01, 1, 0, 0, 0, 0x04, 0x06, 0x01, 0, 0, 0, 0, 0x05, 0x09,
+                ],
+            ),
+            (
+                "!~1-1",
+                vec![1],
+                vec![0x01, 0, 0, 0, 0, 0x07, 0x08, 0x01, 0, 0, 0, 0, 0x03, 0x09],
+            ),
+        ];
+
+        for (input, constants, code) in tests {
+            let chunk = compile(&parse(input));
+            assert_eq!(constants, chunk.constants);
+            assert_eq!(code, chunk.code);
+        }
+    }
+
+    #[test]
+    fn test_opcode_round_trip() {
+        for byte in 0..=255 {
+            if let Some(op) = Opcode::from_byte(byte) {
+                assert_eq!(byte, op as u8);
+            }
+        }
+        assert_eq!(None, Opcode::from_byte(0));
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let chunk = compile(&parse("-(2*3)/2"));
+        let bytes = chunk.encode();
+
+        assert_eq!(b"CBC\0\x01\x00\x02\x00\x00\x00", &bytes[..10]);
+        assert_eq!(Ok(chunk), Chunk::decode(&bytes));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let bytes = compile(&parse("1+2")).encode();
+        let with_checksum = |mut b: Vec<u8>| {
+            let end = b.len() - 4;
+            let checksum = crc32(&b[..end]);
+            b[end..].copy_from_slice(&checksum.to_le_bytes());
+            b
+        };
+
+        let mut flipped = bytes.clone();
+        flipped[12] ^= 1;
+        let mut version = bytes.clone();
+        version[4] = 2;
+        let mut invalid = bytes.clone();
+        let n = invalid.len();
+        invalid[n - 5] = 0xff;
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+
+        let tests = vec![
+            (b"ELF".to_vec(), BytecodeError::BadMagic),
+            (with_checksum(version), BytecodeError::UnsupportedVersion),
+            (bytes[..bytes.len() - 1].to_vec(), BytecodeError::Truncated),
+            (bytes[..20].to_vec(), BytecodeError::Truncated),
+            (flipped, BytecodeError::ChecksumMismatch),
+            (trailing, BytecodeError::TrailingData),
+            (with_checksum(invalid), BytecodeError::InvalidCode),
+        ];
+
+        for (input, want) in tests {
+            assert_eq!(Err(want), Chunk::decode(&input));
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let chunk = |code: Vec<u8>| Chunk {
+            constants: vec![1],
+            code,
+        };
+
+        let tests = vec![
+            (vec![0x09], true),
+            (vec![0x01, 0, 0, 0, 0, 0x06, 0x09], true),
+            (vec![], false),
+            (vec![0x01, 0, 0, 0, 0], false),
+            (vec![0x01, 0, 0], false),
+            (vec![0x01, 1, 0, 0, 0, 0x09], false),
+            (vec![0x01, 0, 0, 0, 0, 0x02, 0x09], false),
+            (vec![0x01, 0, 0, 0, 0, 0x01, 0, 0, 0, 0, 0x09], false),
+            (vec![0x09, 0x09], false),
+            (vec![0x00, 0x09], false),
+        ];
+
+        for (code, valid) in tests {
+            assert_eq!(valid, chunk(code).validate().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(0xcbf4_3926, crc32(b"123456789"));
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let want = r#"constants:
+  0: 6
+  1: 7
+code:
+  0000 push 0 ; 6
+  0005 push 1 ; 7
+  000a mul
+  000b not
+  000c return
+"#;
+
+        assert_eq!(want, compile(&parse("!(6*7)")).disassemble());
+    }
+}
@@ -0,0 +1,474 @@
+use crate::compiler::EXPRESSION;
+use crate::ir::{BinaryOp, Function, Instruction as Ir, UnaryOp, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Register {
+    X(u8),
+    Sp,
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Register::X(n) => write!(f, "x{}", n),
+            Register::Sp => write!(f, "sp"),
+        }
+    }
+}
+
+/// The subset of AArch64 the compiler emits, destination first.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Instruction {
+    Mov(Register, Register),
+    MovImmediate(Register, i64),
+    Neg(Register, Register),
+    Mvn(Register, Register),
+    /// Compares against zero.
+    CmpZero(Register),
+    /// Sets to 1 if the last comparison was equal, 0 otherwise.
+    CsetEq(Register),
+    Add(Register, Register, Register),
+    Sub(Register, Register, Register),
+    Mul(Register, Register, Register),
+    Sdiv(Register, Register, Register),
+    /// `d = a + n * m`
+    Madd(Register, Register, Register, Register),
+    /// `d = a - n * m`
+    Msub(Register, Register, Register, Register),
+    AddImmediate(Register, Register, i64),
+    SubImmediate(Register, Register, i64),
+    Ldr(Register, Register, i64),
+    Str(Register, Register, i64),
+    Ret,
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::Mov(d, n) => write!(f, "mov {}, {}", d, n),
+            // mov only takes immediates a single movz or movn can build.
+            Instruction::MovImmediate(d, i) if (-65536..=65535).contains(&i) => {
+                write!(f, "mov {}, #{}", d, i)
+            }
+            Instruction::MovImmediate(d, i) => write!(f, "ldr {}, ={}", d, i),
+            Instruction::Neg(d, n) => write!(f, "neg {}, {}", d, n),
+            Instruction::Mvn(d, n) => write!(f, "mvn {}, {}", d, n),
+            Instruction::CmpZero(n) => write!(f, "cmp {}, #0", n),
+            Instruction::CsetEq(d) => write!(f, "cset {}, eq", d),
+            Instruction::Add(d, n, m) => write!(f, "add {}, {}, {}", d, n, m),
+            Instruction::Sub(d, n, m) => write!(f, "sub {}, {}, {}", d, n, m),
+            Instruction::Mul(d, n, m) => write!(f, "mul {}, {}, {}", d, n, m),
+            Instruction::Sdiv(d, n, m) => write!(f, "sdiv {}, {}, {}", d, n, m),
+            Instruction::Madd(d, n, m, a) => write!(f, "madd {}, {}, {}, {}", d, n, m, a),
+            Instruction::Msub(d, n, m, a) => write!(f, "msub {}, {}, {}, {}", d, n, m, a),
+            Instruction::AddImmediate(d, n, i) => write!(f, "add {}, {}, #{}", d, n, i),
+            Instruction::SubImmediate(d, n, i) => write!(f, "sub {}, {}, #{}", d, n, i),
+            Instruction::Ldr(t, n, 0) => write!(f, "ldr {}, [{}]", t, n),
+            Instruction::Ldr(t, n, i) => write!(f, "ldr {}, [{}, #{}]", t, n, i),
+            Instruction::Str(t, n, 0) => write!(f, "str {}, [{}]", t, n),
+            Instruction::Str(t, n, i) => write!(f, "str {}, [{}, #{}]", t, n, i),
+            Instruction::Ret => write!(f, "ret"),
+        }
+    }
+}
+
+const MAIN: &str = r#".global main
+main:
+  stp x29, x30, [sp, #-16]!
+  mov x29, sp
+  bl {expression}
+  mov x1, x0
+  adrp x0, .Lformat
+  add x0, x0, :lo12:.Lformat
+  bl printf
+  mov x0, #0
+  ldp x29, x30, [sp], #16
+  ret
+.section .rodata
+.Lformat:
+  .asciz "%ld\n"
+.text
+"#;
+
+pub fn print(symbol: &str, code: &[Instruction]) -> String {
+    let mut s = String::new();
+
+    s.push_str(&format!(".global {}\n", symbol));
+    s.push_str(&format!("{}:\n", symbol));
+    for i in code {
+        s.push_str(&format!("  {}\n", i));
+    }
+
+    s
+}
+
+/// Prints `code` as the function `main` calls, and a `main` printing its
+/// result with the C library's `printf`.
+pub fn print_program(code: &[Instruction]) -> String {
+    let mut s = MAIN.replace("{expression}", EXPRESSION);
+    s.push_str(&print(EXPRESSION, code));
+    s
+}
+
+// x9 to x15 are caller-saved temporaries; x1 to x3 are free as scratch
+// registers because main takes no arguments.
+const STACK_REGISTERS: u8 = 7;
+const FIRST_STACK_REGISTER: u8 = 9;
+const SCRATCH: [Register; 3] = [Register::X(1), Register::X(2), Register::X(3)];
+
+/// Generates code that leaves the result of `f` in x0.
+///
+/// Operands are kept on a stack mapped onto x9 to x15 and spilled to the
+/// frame beyond that depth. A multiplication feeding an addition or
+/// subtraction is fused into `madd` or `msub`.
+///
+/// `sdiv` yields 0 for a division by zero instead of trapping.
+pub fn generate(f: &Function) -> Vec<Instruction> {
+    let mut g = Generator {
+        users: BTreeMap::new(),
+        stack: vec![],
+        depth: 0,
+        body: vec![],
+    };
+
+    for (n, i) in f.instructions.iter().enumerate() {
+        for v in i.uses() {
+            g.users.insert(v, n);
+        }
+    }
+
+    for i in &f.instructions {
+        g.generate_instruction(i, f);
+    }
+
+    let frame = match g.depth.saturating_sub(STACK_REGISTERS as usize) {
+        0 => 0,
+        // sp has to stay 16-byte aligned.
+        slots => (slots as i64 * 8 + 15) / 16 * 16,
+    };
+
+    let mut code = vec![];
+    if frame > 0 {
+        code.push(Instruction::SubImmediate(Register::Sp, Register::Sp, frame));
+    }
+    code.append(&mut g.body);
+    code.push(Instruction::Mov(Register::X(0), location(0)));
+    if frame > 0 {
+        code.push(Instruction::AddImmediate(Register::Sp, Register::Sp, frame));
+    }
+    code.push(Instruction::Ret);
+
+    code
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum Entry {
+    Value(Value),
+    /// One of the two operands of a multiplication that is deferred until
+    /// its user, identified by the product value.
+    Factor(Value),
+}
+
+struct Generator {
+    users: BTreeMap<Value, usize>,
+    stack: Vec<Entry>,
+    depth: usize,
+    body: Vec<Instruction>,
+}
+
+fn location(depth: usize) -> Register {
+    Register::X(FIRST_STACK_REGISTER + depth as u8)
+}
+
+fn slot(depth: usize) -> i64 {
+    (depth as i64 - STACK_REGISTERS as i64) * 8
+}
+
+impl Generator {
+    fn generate_instruction(&mut self, i: &Ir, f: &Function) {
+        match *i {
+            Ir::Const(d, i) => {
+                let top = self.stack.len();
+                let r = self.target(top);
+                self.body.push(Instruction::MovImmediate(r, i));
+                self.push(top, r, Entry::Value(d));
+            }
+            Ir::Unary(d, op, _) => {
+                let top = self.stack.len() - 1;
+                let n = self.read(top, SCRATCH[0]);
+                let r = self.target(top);
+                match op {
+                    UnaryOp::Neg => self.body.push(Instruction::Neg(r, n)),
+                    UnaryOp::Not => self.body.push(Instruction::Mvn(r, n)),
+                    UnaryOp::LogicalNot => {
+                        self.body.push(Instruction::CmpZero(n));
+                        self.body.push(Instruction::CsetEq(r));
+                    }
+                }
+                self.stack.pop();
+                self.push(top, r, Entry::Value(d));
+            }
+            Ir::Binary(d, BinaryOp::Mul, _, _) if self.is_fused(d, f) => {
+                let top = self.stack.len();
+                self.stack[top - 2] = Entry::Factor(d);
+                self.stack[top - 1] = Entry::Factor(d);
+            }
+            Ir::Binary(d, op, a, b) => {
+                let fused = [a, b]
+                    .iter()
+                    .copied()
+                    .find(|v| self.stack.contains(&Entry::Factor(*v)));
+
+                match fused {
+                    Some(m) => self.generate_fused(d, op, m),
+                    None => self.generate_binary(d, op, a),
+                }
+            }
+        }
+    }
+
+    /// Whether the product `m` is only used by an addition, or as the
+    /// subtrahend of a subtraction. Only one operand of the user can be
+    /// deferred.
+    fn is_fused(&self, m: Value, f: &Function) -> bool {
+        let (other, fusable) = match self.users.get(&m).map(|n| &f.instructions[*n]) {
+            Some(Ir::Binary(_, BinaryOp::Add, a, b)) if *a == m => (*b, true),
+            Some(Ir::Binary(_, BinaryOp::Add, a, _)) => (*a, true),
+            Some(Ir::Binary(_, BinaryOp::Sub, a, b)) => (*a, *b == m),
+            _ => return false,
+        };
+        fusable && !self.stack.contains(&Entry::Factor(other))
+    }
+
+    fn generate_binary(&mut self, d: Value, op: BinaryOp, a: Value) {
+        let (x, y) = (self.stack.len() - 2, self.stack.len() - 1);
+        let (a, b) = match self.stack[x] == Entry::Value(a) {
+            true => (x, y),
+            false => (y, x),
+        };
+
+        let n = self.read(a, SCRATCH[0]);
+        let m = self.read(b, SCRATCH[1]);
+        let r = self.target(x);
+        match op {
+            BinaryOp::Add => self.body.push(Instruction::Add(r, n, m)),
+            BinaryOp::Sub => self.body.push(Instruction::Sub(r, n, m)),
+            BinaryOp::Mul => self.body.push(Instruction::Mul(r, n, m)),
+            BinaryOp::Div => self.body.push(Instruction::Sdiv(r, n, m)),
+        }
+
+        self.stack.truncate(x);
+        self.push(x, r, Entry::Value(d));
+    }
+
+    fn generate_fused(&mut self, d: Value, op: BinaryOp, m: Value) {
+        let bottom = self.stack.len() - 3;
+        let depths: Vec<usize> = (bottom..self.stack.len()).collect();
+        let (factors, addend): (Vec<usize>, Vec<usize>) = depths
+            .into_iter()
+            .partition(|n| self.stack[*n] == Entry::Factor(m));
+
+        let a = self.read(addend[0], SCRATCH[0]);
+        let n = self.read(factors[0], SCRATCH[1]);
+        let m = self.read(factors[1], SCRATCH[2]);
+        let r = self.target(bottom);
+        match op {
+            BinaryOp::Add => self.body.push(Instruction::Madd(r, n, m, a)),
+            _ => self.body.push(Instruction::Msub(r, n, m, a)),
+        }
+
+        self.stack.truncate(bottom);
+        self.push(bottom, r, Entry::Value(d));
+    }
+
+    /// The register holding stack entry `depth`, loading it into `scratch`
+    /// if it was spilled.
+    fn read(&mut self, depth: usize, scratch: Register) -> Register {
+        match depth < STACK_REGISTERS as usize {
+            true => location(depth),
+            false => {
+                self.body
+                    .push(Instruction::Ldr(scratch, Register::Sp, slot(depth)));
+                scratch
+            }
+        }
+    }
+
+    /// The register to compute stack entry `depth` into.
+    fn target(&self, depth: usize) -> Register {
+        match depth < STACK_REGISTERS as usize {
+            true => location(depth),
+            false => SCRATCH[0],
+        }
+    }
+
+    /// Stores `r` into stack entry `depth` if that entry lives in memory.
+    fn push(&mut self, depth: usize, r: Register, e: Entry) {
+        if depth >= STACK_REGISTERS as usize {
+            self.body
+                .push(Instruction::Str(r, Register::Sp, slot(depth)));
+        }
+        self.stack.push(e);
+        self.depth = self.depth.max(self.stack.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::Statement;
+    use crate::compiler::aarch64::Instruction::*;
+    use crate::compiler::aarch64::Register::*;
+    use crate::compiler::aarch64::*;
+    use crate::ir::lower;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Statement {
+        Parser::new(Lexer::new(input)).parse().unwrap().remove(0)
+    }
+
+    #[test]
+    fn test_generate() {
+        let tests = vec![
+            ("7", vec![MovImmediate(X(9), 7), Mov(X(0), X(9)), Ret]),
+            (
+                "8/-(3-2)",
+                vec![
+                    MovImmediate(X(9), 3),
+                    MovImmediate(X(10), 2),
+                    Sub(X(9), X(9), X(10)),
+                    Neg(X(9), X(9)),
+                    MovImmediate(X(10), 8),
+                    Sdiv(X(9), X(10), X(9)),
+                    Mov(X(0), X(9)),
+                    Ret,
+                ],
+            ),
+            (
+                "!~0",
+                vec![
+                    MovImmediate(X(9), 0),
+                    Mvn(X(9), X(9)),
+                    CmpZero(X(9)),
+                    CsetEq(X(9)),
+                    Mov(X(0), X(9)),
+                    Ret,
+                ],
+            ),
+        ];
+
+        for (input, want) in tests {
+            assert_eq!(want, generate(&lower(&parse(input))));
+        }
+    }
+
+    #[test]
+    fn test_generate_fused() {
+        let tests = vec![
+            (
+                "1+2*3",
+                vec![
+                    MovImmediate(X(9), 2),
+                    MovImmediate(X(10), 3),
+                    MovImmediate(X(11), 1),
+                    Madd(X(9), X(9), X(10), X(11)),
+                    Mov(X(0), X(9)),
+                    Ret,
+                ],
+            ),
+            (
+                "7-2*3",
+                vec![
+                    MovImmediate(X(9), 2),
+                    MovImmediate(X(10), 3),
+                    MovImmediate(X(11), 7),
+                    Msub(X(9), X(9), X(10), X(11)),
+                    Mov(X(0), X(9)),
+                    Ret,
+                ],
+            ),
+            (
+                "2*3-7",
+                vec![
+                    MovImmediate(X(9), 2),
+                    MovImmediate(X(10), 3),
+                    Mul(X(9), X(9), X(10)),
+                    MovImmediate(X(10), 7),
+                    Sub(X(9), X(9), X(10)),
+                    Mov(X(0), X(9)),
+                    Ret,
+                ],
+            ),
+            (
+                "2*3+4*5",
+                vec![
+                    MovImmediate(X(9), 2),
+                    MovImmediate(X(10), 3),
+                    MovImmediate(X(11), 4),
+                    MovImmediate(X(12), 5),
+                    Mul(X(11), X(11), X(12)),
+                    Madd(X(9), X(9), X(10), X(11)),
+                    Mov(X(0), X(9)),
+                    Ret,
+                ],
+            ),
+        ];
+
+        for (input, want) in tests {
+            assert_eq!(want, generate(&lower(&parse(input))));
+        }
+    }
+
+    #[test]
+    fn test_generate_spill() {
+        let mut input = "1".to_string();
+        for _ in 0..7 {
+            input = format!("({}+{})", input, input);
+        }
+        let code = generate(&lower(&parse(&input)));
+
+        assert_eq!(SubImmediate(Sp, Sp, 16), code[0]);
+        assert!(code.contains(&Str(X(1), Sp, 0)));
+        assert!(code.contains(&Ldr(X(2), Sp, 0)));
+        assert_eq!(
+            vec![Mov(X(0), X(9)), AddImmediate(Sp, Sp, 16), Ret],
+            code[code.len() - 3..].to_vec()
+        );
+    }
+
+    #[test]
+    fn test_print() {
+        let tests = vec![
+            ("mov x9, #-5", MovImmediate(X(9), -5)),
+            ("ldr x9, =4294967296", MovImmediate(X(9), 1 << 32)),
+            ("cset x10, eq", CsetEq(X(10))),
+            ("msub x9, x10, x11, x9", Msub(X(9), X(10), X(11), X(9))),
+            ("sub sp, sp, #16", SubImmediate(Sp, Sp, 16)),
+            ("ldr x2, [sp]", Ldr(X(2), Sp, 0)),
+            ("str x1, [sp, #8]", Str(X(1), Sp, 8)),
+        ];
+
+        for (want, i) in tests {
+            assert_eq!(want, i.to_string());
+        }
+
+        let want = r#".global main
+main:
+  mov x0, #42
+  ret
+"#;
+        assert_eq!(want, print("main", &[MovImmediate(X(0), 42), Ret]));
+    }
+
+    #[test]
+    fn test_print_program() {
+        let code = print_program(&generate(&lower(&parse("5"))));
+
+        assert!(code.starts_with(".global main\nmain:\n"));
+        assert!(code.contains("  bl calc_expr\n"));
+        assert!(code.contains("  bl printf\n"));
+        assert!(code.contains("\n.global calc_expr\ncalc_expr:\n"));
+    }
+}
@@ -0,0 +1,294 @@
+use crate::compiler::{RETURNED_DIVIDE_BY_ZERO, RETURNED_OVERFLOW};
+use crate::error::RuntimeError;
+use crate::ir::{BinaryOp, Function, Instruction, UnaryOp, Value};
+
+const INCLUDES: &str = r#"#include <inttypes.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+"#;
+
+const OVERFLOW: &str = r#"
+static void calc_overflow(void) {
+    fputs("{overflow}\n", stderr);
+    exit(EXIT_FAILURE);
+}
+"#;
+
+const NEG: &str = r#"
+static int64_t calc_neg(int64_t a) {
+    if (a == INT64_MIN) {
+        calc_overflow();
+    }
+    return -a;
+}
+"#;
+
+const ADD: &str = r#"
+static int64_t calc_add(int64_t a, int64_t b) {
+    if (b > 0 ? a > INT64_MAX - b : a < INT64_MIN - b) {
+        calc_overflow();
+    }
+    return a + b;
+}
+"#;
+
+const SUB: &str = r#"
+static int64_t calc_sub(int64_t a, int64_t b) {
+    if (b < 0 ? a > INT64_MAX + b : a < INT64_MIN + b) {
+        calc_overflow();
+    }
+    return a - b;
+}
+"#;
+
+const MUL: &str = r#"
+static int64_t calc_mul(int64_t a, int64_t b) {
+    if (a > 0 ? (b > 0 ? a > INT64_MAX / b : b < INT64_MIN / a)
+              : (b > 0 ? a < INT64_MIN / b : a != 0 && b < INT64_MAX / a)) {
+        calc_overflow();
+    }
+    return a * b;
+}
+"#;
+
+const DIV: &str = r#"
+static int64_t calc_div(int64_t a, int64_t b) {
+    if (b == 0) {
+        fputs("{divide_by_zero}\n", stderr);
+        exit(EXIT_FAILURE);
+    }
+    if (a == INT64_MIN && b == -1) {
+        calc_overflow();
+    }
+    return a / b;
+}
+"#;
+
+const MAIN: &str = r#"
+int main(void) {
+    printf("%" PRId64 "\n", {name}_expr());
+    return 0;
+}
+"#;
+
+const HEADER: &str = r#"#ifndef {guard}
+#define {guard}
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+{declarations}
+#ifdef __cplusplus
+}
+#endif
+
+#endif
+"#;
+
+/// Prints `f` as a C function `int64_t <name>_expr(void)`, and a `main`
+/// printing its result if `main` is set.
+///
+/// Arithmetic goes through helpers that, like the evaluator, treat a
+/// division by zero and an overflow as errors: they print the evaluator's
+/// message to stderr and exit with `EXIT_FAILURE`. Only the helpers `f`
+/// uses are printed.
+pub fn print(f: &Function, main: bool) -> String {
+    let mut code = INCLUDES.to_string();
+
+    let used: Vec<&str> = [NEG, ADD, SUB, MUL, DIV]
+        .iter()
+        .filter(|source| f.instructions.iter().any(|i| helper(i) == Some(source)))
+        .copied()
+        .collect();
+    if !used.is_empty() {
+        code.push_str(&OVERFLOW.replace("{overflow}", &RuntimeError::Overflow.to_string()));
+    }
+    for source in used {
+        code.push_str(&source.replace("{divide_by_zero}", &RuntimeError::DivideByZero.to_string()));
+    }
+
+    code.push_str(&format!("\nint64_t {}_expr(void) {{\n", f.name));
+    for i in &f.instructions {
+        code.push_str(&format!(
+            "    const int64_t {} = {};\n",
+            name(i.defines()),
+            expression(i)
+        ));
+    }
+    code.push_str(&format!("    return {};\n}}\n", name(f.result)));
+
+    if main {
+        code.push_str(&MAIN.replace("{name}", &f.name));
+    }
+
+    code
+}
+
+const OBJECT_DECLARATIONS: &str = r#"/* The result, or else an error of {SYMBOL}_DIVIDE_BY_ZERO or
+   {SYMBOL}_OVERFLOW, which the value is not meaningful for. */
+struct {symbol}_result {
+    int64_t value;
+    int64_t error;
+};
+
+#define {SYMBOL}_DIVIDE_BY_ZERO {divide_by_zero}
+#define {SYMBOL}_OVERFLOW {overflow}
+
+struct {symbol}_result {symbol}(void);
+"#;
+
+/// Prints a header declaring `int64_t <name>_expr(void)`, the function
+/// `print` defines for a function named `name`.
+pub fn header(name: &str) -> String {
+    let declarations = format!("int64_t {}_expr(void);\n", name);
+    header_for(&format!("{}_expr", name), &declarations)
+}
+
+/// Prints a header declaring the function `elf::object` defines as
+/// `symbol` when generated with `return_errors`: it returns a struct of the
+/// result and an error code, which is 0 if there was no error.
+pub fn object_header(symbol: &str) -> String {
+    let declarations = OBJECT_DECLARATIONS
+        .replace("{SYMBOL}", &symbol.to_uppercase())
+        .replace("{symbol}", symbol)
+        .replace("{divide_by_zero}", &RETURNED_DIVIDE_BY_ZERO.to_string())
+        .replace("{overflow}", &RETURNED_OVERFLOW.to_string());
+    header_for(symbol, &declarations)
+}
+
+fn header_for(name: &str, declarations: &str) -> String {
+    let guard = format!("{}_H", name.to_uppercase());
+    HEADER
+        .replace("{guard}", &guard)
+        .replace("{declarations}", declarations)
+}
+
+/// The source of the helper computing `i`, if it needs one.
+fn helper(i: &Instruction) -> Option<&'static str> {
+    match *i {
+        Instruction::Unary(_, UnaryOp::Neg, _) => Some(NEG),
+        Instruction::Binary(_, BinaryOp::Add, _, _) => Some(ADD),
+        Instruction::Binary(_, BinaryOp::Sub, _, _) => Some(SUB),
+        Instruction::Binary(_, BinaryOp::Mul, _, _) => Some(MUL),
+        Instruction::Binary(_, BinaryOp::Div, _, _) => Some(DIV),
+        _ => None,
+    }
+}
+
+fn name(v: Value) -> String {
+    format!("v{}", v.0)
+}
+
+fn expression(i: &Instruction) -> String {
+    match *i {
+        Instruction::Const(_, i64::MIN) => "INT64_MIN".to_string(),
+        Instruction::Const(_, i) => format!("INT64_C({})", i),
+        Instruction::Unary(_, UnaryOp::Neg, a) => format!("calc_neg({})", name(a)),
+        Instruction::Unary(_, UnaryOp::Not, a) => format!("~{}", name(a)),
+        Instruction::Unary(_, UnaryOp::LogicalNot, a) => format!("{} == 0", name(a)),
+        Instruction::Binary(_, op, a, b) => {
+            let helper = match op {
+                BinaryOp::Add => "calc_add",
+                BinaryOp::Sub => "calc_sub",
+                BinaryOp::Mul => "calc_mul",
+                BinaryOp::Div => "calc_div",
+            };
+            format!("{}({}, {})", helper, name(a), name(b))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::Statement;
+    use crate::compiler::c::{header, object_header, print};
+    use crate::ir::lower;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Statement {
+        Parser::new(Lexer::new(input)).parse().unwrap().remove(0)
+    }
+
+    #[test]
+    fn test_print_expression() {
+        let tests = vec![
+            ("5", vec!["    const int64_t v0 = INT64_C(5);"]),
+            (
+                "!~-(1)",
+                vec![
+                    "    const int64_t v0 = INT64_C(1);",
+                    "    const int64_t v1 = calc_neg(v0);",
+                    "    const int64_t v2 = ~v1;",
+                    "    const int64_t v3 = v2 == 0;",
+                ],
+            ),
+            (
+                "(1+2)/3",
+                vec![
+                    "    const int64_t v0 = INT64_C(1);",
+                    "    const int64_t v1 = INT64_C(2);",
+                    "    const int64_t v2 = calc_add(v0, v1);",
+                    "    const int64_t v3 = INT64_C(3);",
+                    "    const int64_t v4 = calc_div(v2, v3);",
+                ],
+            ),
+        ];
+
+        for (input, want) in tests {
+            let code = print(&lower(&parse(input)), false);
+            let body: Vec<&str> = code
+                .lines()
+                .skip_while(|l| !l.starts_with("int64_t main_expr"))
+                .skip(1)
+                .take(want.len())
+                .collect();
+            assert_eq!(want, body);
+        }
+    }
+
+    #[test]
+    fn test_print_main() {
+        let f = lower(&parse("1"));
+
+        assert!(!print(&f, false).contains("int main(void)"));
+        assert!(print(&f, true).ends_with(
+            r#"
+int main(void) {
+    printf("%" PRId64 "\n", main_expr());
+    return 0;
+}
+"#
+        ));
+        assert!(!print(&f, true).contains("calc_div"));
+        assert!(!print(&f, true).contains("calc_overflow"));
+
+        let f = lower(&parse("1/0"));
+        assert!(print(&f, true).contains(r#"fputs("Divide a number by 0.\n", stderr);"#));
+        assert!(print(&f, true).contains(r#"fputs("Found an integer overflow.\n", stderr);"#));
+        assert!(!print(&f, true).contains("calc_mul"));
+    }
+
+    #[test]
+    fn test_header() {
+        let code = header("calc_formula");
+
+        assert!(code.starts_with("#ifndef CALC_FORMULA_EXPR_H\n#define CALC_FORMULA_EXPR_H\n"));
+        assert!(code.contains("\nint64_t calc_formula_expr(void);\n"));
+        assert!(code.contains("extern \"C\" {"));
+    }
+
+    #[test]
+    fn test_object_header() {
+        let code = object_header("calc_formula");
+
+        assert!(code.starts_with("#ifndef CALC_FORMULA_H\n#define CALC_FORMULA_H\n"));
+        assert!(code.contains("\nstruct calc_formula_result calc_formula(void);\n"));
+        assert!(code.contains("\n#define CALC_FORMULA_DIVIDE_BY_ZERO 1\n"));
+        assert!(code.contains("\n#define CALC_FORMULA_OVERFLOW 2\n"));
+    }
+}
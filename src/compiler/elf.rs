@@ -0,0 +1,400 @@
+use crate::compiler::encoder::encode;
+use crate::compiler::x86::{Assembly, Instruction, Operand, Register};
+use crate::compiler::SYS_EXIT;
+
+/// The address executables are loaded at.
+const BASE: u64 = 0x40_0000;
+const HEADER_SIZE: u16 = 64;
+const PROGRAM_HEADER_SIZE: u16 = 56;
+const SECTION_HEADER_SIZE: u16 = 64;
+const SYMBOL_SIZE: u64 = 24;
+
+const ET_REL: u16 = 1;
+const ET_EXEC: u16 = 2;
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHF_ALLOC: u64 = 2;
+const SHF_EXECINSTR: u64 = 4;
+const STB_GLOBAL: u8 = 1;
+const STT_FUNC: u8 = 2;
+
+/// Writes a static Linux executable whose `_start` calls `assembly` and
+/// exits with its result as the status, like a C `main` would.
+///
+/// The file is a single read-only, executable segment with no sections,
+/// which is all the kernel needs to run it.
+pub fn executable(assembly: &Assembly) -> Vec<u8> {
+    let rax = Operand::Register(Register::Rax);
+    let rdi = Operand::Register(Register::Rdi);
+    let mut code = vec![
+        Instruction::Call("main"),
+        Instruction::Mov(rdi, rax),
+        Instruction::Mov(rax, Operand::Immediate(SYS_EXIT)),
+        Instruction::Syscall,
+        Instruction::Label("main"),
+    ];
+    code.extend(&assembly.instructions);
+    let text = encode(&code);
+
+    let offset = (HEADER_SIZE + PROGRAM_HEADER_SIZE) as u64;
+    let size = offset + text.len() as u64;
+    let mut bytes = header(ET_EXEC, BASE + offset, 1, 0, 0, 0);
+
+    // The segment maps the whole file, headers included.
+    bytes.extend(&1u32.to_le_bytes()); // PT_LOAD
+    bytes.extend(&5u32.to_le_bytes()); // PF_R | PF_X
+    bytes.extend(&0u64.to_le_bytes()); // p_offset
+    bytes.extend(&BASE.to_le_bytes()); // p_vaddr
+    bytes.extend(&BASE.to_le_bytes()); // p_paddr
+    bytes.extend(&size.to_le_bytes()); // p_filesz
+    bytes.extend(&size.to_le_bytes()); // p_memsz
+    bytes.extend(&0x1000u64.to_le_bytes()); // p_align
+
+    bytes.extend(text);
+    bytes
+}
+
+/// Writes a relocatable object file defining `assembly.symbol` as a global
+/// function, for linking into C or C++ programs.
+///
+/// The code refers to nothing outside itself, so the object needs no
+/// relocations. An empty `.note.GNU-stack` marks the stack as not
+/// executable.
+pub fn object(assembly: &Assembly) -> Vec<u8> {
+    let text = encode(&assembly.instructions);
+
+    let mut strtab = vec![0];
+    strtab.extend(assembly.symbol.as_bytes());
+    strtab.push(0);
+
+    let mut symtab = vec![0; SYMBOL_SIZE as usize];
+    symtab.extend(&1u32.to_le_bytes()); // st_name
+    symtab.push(STB_GLOBAL << 4 | STT_FUNC);
+    symtab.push(0); // STV_DEFAULT
+    symtab.extend(&1u16.to_le_bytes()); // .text
+    symtab.extend(&0u64.to_le_bytes()); // st_value
+    symtab.extend(&(text.len() as u64).to_le_bytes());
+
+    let names = [
+        ".text",
+        ".symtab",
+        ".strtab",
+        ".shstrtab",
+        ".note.GNU-stack",
+    ];
+    let mut shstrtab = vec![0];
+    let mut name_offsets = vec![];
+    for name in names.iter() {
+        name_offsets.push(shstrtab.len() as u32);
+        shstrtab.extend(name.as_bytes());
+        shstrtab.push(0);
+    }
+
+    // Contents follow the file header, each aligned to 8 bytes.
+    let contents = [&text, &symtab, &strtab, &shstrtab];
+    let mut offsets = vec![];
+    let mut offset = HEADER_SIZE as u64;
+    for c in contents.iter() {
+        offsets.push(offset);
+        offset = align(offset + c.len() as u64, 8);
+    }
+    let shoff = offset;
+
+    let mut bytes = header(ET_REL, 0, 0, shoff, names.len() as u16 + 1, 4);
+    for c in contents.iter() {
+        bytes.extend(c.iter());
+        bytes.resize(align(bytes.len() as u64, 8) as usize, 0);
+    }
+
+    let sections = [
+        Section::default(),
+        Section {
+            name: name_offsets[0],
+            kind: SHT_PROGBITS,
+            flags: SHF_ALLOC | SHF_EXECINSTR,
+            offset: offsets[0],
+            size: text.len() as u64,
+            align: 16,
+            ..Section::default()
+        },
+        Section {
+            name: name_offsets[1],
+            kind: SHT_SYMTAB,
+            offset: offsets[1],
+            size: symtab.len() as u64,
+            // .strtab, and the index of the first global symbol.
+            link: 3,
+            info: 1,
+            align: 8,
+            entry_size: SYMBOL_SIZE,
+            ..Section::default()
+        },
+        Section {
+            name: name_offsets[2],
+            kind: SHT_STRTAB,
+            offset: offsets[2],
+            size: strtab.len() as u64,
+            align: 1,
+            ..Section::default()
+        },
+        Section {
+            name: name_offsets[3],
+            kind: SHT_STRTAB,
+            offset: offsets[3],
+            size: shstrtab.len() as u64,
+            align: 1,
+            ..Section::default()
+        },
+        Section {
+            name: name_offsets[4],
+            kind: SHT_PROGBITS,
+            offset: shoff,
+            align: 1,
+            ..Section::default()
+        },
+    ];
+    for section in sections.iter() {
+        section.write(&mut bytes);
+    }
+
+    bytes
+}
+
+/// An entry of the section header table.
+#[derive(Default)]
+struct Section {
+    name: u32,
+    kind: u32,
+    flags: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    align: u64,
+    entry_size: u64,
+}
+
+impl Section {
+    fn write(&self, bytes: &mut Vec<u8>) {
+        bytes.extend(&self.name.to_le_bytes());
+        bytes.extend(&self.kind.to_le_bytes());
+        bytes.extend(&self.flags.to_le_bytes());
+        bytes.extend(&0u64.to_le_bytes()); // sh_addr
+        bytes.extend(&self.offset.to_le_bytes());
+        bytes.extend(&self.size.to_le_bytes());
+        bytes.extend(&self.link.to_le_bytes());
+        bytes.extend(&self.info.to_le_bytes());
+        bytes.extend(&self.align.to_le_bytes());
+        bytes.extend(&self.entry_size.to_le_bytes());
+    }
+}
+
+/// The ELF file header for x86-64 Linux. Program headers follow it
+/// directly.
+fn header(kind: u16, entry: u64, phnum: u16, shoff: u64, shnum: u16, shstrndx: u16) -> Vec<u8> {
+    let mut bytes = vec![];
+
+    // e_ident: 64-bit, little endian, version 1, System V ABI.
+    bytes.extend(b"\x7fELF");
+    bytes.extend(&[2, 1, 1, 0]);
+    bytes.extend(&[0; 8]);
+    bytes.extend(&kind.to_le_bytes());
+    bytes.extend(&0x3eu16.to_le_bytes()); // EM_X86_64
+    bytes.extend(&1u32.to_le_bytes());
+    bytes.extend(&entry.to_le_bytes());
+    let phoff = match phnum {
+        0 => 0,
+        _ => HEADER_SIZE as u64,
+    };
+    bytes.extend(&phoff.to_le_bytes());
+    bytes.extend(&shoff.to_le_bytes());
+    bytes.extend(&0u32.to_le_bytes()); // e_flags
+    bytes.extend(&HEADER_SIZE.to_le_bytes());
+    bytes.extend(&PROGRAM_HEADER_SIZE.to_le_bytes());
+    bytes.extend(&phnum.to_le_bytes());
+    bytes.extend(&SECTION_HEADER_SIZE.to_le_bytes());
+    bytes.extend(&shnum.to_le_bytes());
+    bytes.extend(&shstrndx.to_le_bytes());
+
+    bytes
+}
+
+fn align(n: u64, to: u64) -> u64 {
+    n.div_ceil(to) * to
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::elf::{executable, object};
+    use crate::compiler::x86::Assembly;
+    use crate::compiler::x86::Instruction::*;
+    use crate::compiler::x86::Operand::*;
+    use crate::compiler::x86::Register::*;
+
+    fn assembly(instructions: Vec<crate::compiler::x86::Instruction>) -> Assembly {
+        Assembly {
+            symbol: "main".to_string(),
+            instructions,
+        }
+    }
+
+    #[test]
+    fn test_executable() {
+        let bytes = executable(&assembly(vec![Mov(Register(Rax), Immediate(42)), Ret]));
+
+        assert_eq!(b"\x7fELF\x02\x01\x01", &bytes[..7]);
+        // e_entry points right after the headers.
+        assert_eq!(0x40_0078u64.to_le_bytes(), bytes[24..32]);
+        // p_filesz covers the whole file.
+        assert_eq!((bytes.len() as u64).to_le_bytes(), bytes[96..104]);
+        assert_eq!(
+            vec![
+                0xe8, 0x0c, 0x00, 0x00, 0x00, // call main
+                0x48, 0x89, 0xc7, // mov rdi, rax
+                0x48, 0xc7, 0xc0, 0x3c, 0x00, 0x00, 0x00, // mov rax, 60
+                0x0f, 0x05, // syscall
+                0x48, 0xc7, 0xc0, 0x2a, 0x00, 0x00, 0x00, // main: mov rax, 42
+                0xc3, // ret
+            ],
+            bytes[120..].to_vec()
+        );
+    }
+
+    #[test]
+    fn test_object() {
+        let mut a = assembly(vec![Mov(Register(Rax), Immediate(42)), Ret]);
+        a.symbol = "calc_formula".to_string();
+        let bytes = object(&a);
+
+        // ET_REL, with the section headers at e_shoff.
+        assert_eq!(1u16.to_le_bytes(), bytes[16..18]);
+        let shoff = u64::from_le_bytes([
+            bytes[40], bytes[41], bytes[42], bytes[43], bytes[44], bytes[45], bytes[46], bytes[47],
+        ]) as usize;
+        assert_eq!(shoff + 6 * 64, bytes.len());
+        // .text comes right after the file header.
+        assert_eq!(
+            vec![0x48, 0xc7, 0xc0, 0x2a, 0x00, 0x00, 0x00, 0xc3],
+            bytes[64..72].to_vec()
+        );
+        // .strtab follows .text and the two symbols.
+        assert_eq!(b"\0calc_formula\0", &bytes[120..134]);
+    }
+
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    fn test_executable_runs() {
+        use crate::compiler::Compiler;
+        use crate::lexer::Lexer;
+        use crate::parser::Parser;
+        use std::os::unix::fs::PermissionsExt;
+
+        let tests = vec![("5+6*7", 47), ("(3+5)/2", 4), ("8/-(3-2)", 248)];
+
+        for (i, (input, want)) in tests.into_iter().enumerate() {
+            let s = Parser::new(Lexer::new(input)).parse().unwrap().remove(0);
+            let bytes = executable(&Compiler::new().generate(&crate::ir::lower(&s)));
+
+            let path =
+                std::env::temp_dir().join(format!("calculator-elf-{}-{}", std::process::id(), i));
+            std::fs::write(&path, bytes).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+            let status = std::process::Command::new(&path).status().unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!(Some(want), status.code());
+        }
+    }
+
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    fn test_executable_prints() {
+        use crate::compiler::{Allocation, Compiler};
+        use crate::lexer::Lexer;
+        use crate::parser::Parser;
+        use std::os::unix::fs::PermissionsExt;
+
+        let tests = vec![
+            ("5+6*7", "47\n"),
+            ("0", "0\n"),
+            ("8/-(3-2)", "-8\n"),
+            ("100000*100000-1", "9999999999\n"),
+            ("9223372036854775807", "9223372036854775807\n"),
+            ("-9223372036854775807-1", "-9223372036854775808\n"),
+        ];
+
+        for (i, (input, want)) in tests.into_iter().enumerate() {
+            let s = Parser::new(Lexer::new(input)).parse().unwrap().remove(0);
+            let c = Compiler::new()
+                .allocation(Allocation::Registers)
+                .print_result(true);
+            let bytes = executable(&c.generate(&crate::ir::lower(&s)));
+
+            let path = std::env::temp_dir().join(format!(
+                "calculator-elf-print-{}-{}",
+                std::process::id(),
+                i
+            ));
+            std::fs::write(&path, bytes).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+            let output = std::process::Command::new(&path).output().unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!(Some(0), output.status.code());
+            assert_eq!(want, String::from_utf8(output.stdout).unwrap());
+        }
+    }
+
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    fn test_executable_traps() {
+        use crate::compiler::{Allocation, Compiler, TRAP_STATUS};
+        use crate::error::RuntimeError;
+        use crate::lexer::Lexer;
+        use crate::parser::Parser;
+        use std::os::unix::fs::PermissionsExt;
+
+        let tests = vec![
+            ("1/(2-2)", Some(RuntimeError::DivideByZero)),
+            ("9223372036854775807+1", Some(RuntimeError::Overflow)),
+            ("0-9223372036854775807-2", Some(RuntimeError::Overflow)),
+            ("4611686018427387904*2", Some(RuntimeError::Overflow)),
+            ("-(0-9223372036854775807-1)", Some(RuntimeError::Overflow)),
+            ("(0-9223372036854775807-1)/-1", Some(RuntimeError::Overflow)),
+            ("(0-9223372036854775807-1)/1", None),
+        ];
+
+        for (i, (input, want)) in tests.into_iter().enumerate() {
+            let s = Parser::new(Lexer::new(input)).parse().unwrap().remove(0);
+            let c = Compiler::new()
+                .allocation(Allocation::Registers)
+                .check_division(true)
+                .check_overflow(true)
+                .define_traps(true)
+                .print_result(true);
+            let bytes = executable(&c.generate(&crate::ir::lower(&s)));
+
+            let path = std::env::temp_dir().join(format!(
+                "calculator-elf-trap-{}-{}",
+                std::process::id(),
+                i
+            ));
+            std::fs::write(&path, bytes).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+            let output = std::process::Command::new(&path).output().unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            match want {
+                Some(e) => {
+                    assert_eq!(Some(TRAP_STATUS as i32), output.status.code());
+                    assert_eq!(
+                        format!("{}\n", e),
+                        String::from_utf8(output.stderr).unwrap()
+                    );
+                }
+                None => assert_eq!(Some(0), output.status.code()),
+            }
+        }
+    }
+}
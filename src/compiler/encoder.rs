@@ -0,0 +1,351 @@
+use crate::compiler::x86::{Address, Instruction, Operand, Register};
+use std::collections::BTreeMap;
+
+/// Encodes instructions into x86-64 machine code, resolving labels.
+///
+/// Panics on operand combinations the compiler never generates, such as a
+/// move between two memory operands, and on jumps to undefined labels.
+pub fn encode(code: &[Instruction]) -> Vec<u8> {
+    let mut e = Encoder {
+        bytes: vec![],
+        labels: BTreeMap::new(),
+        fixups: vec![],
+    };
+
+    for i in code {
+        e.encode_instruction(i);
+    }
+
+    for (at, label) in &e.fixups {
+        let target = match e.labels.get(label) {
+            Some(target) => *target,
+            None => panic!("undefined label {}", label),
+        };
+        // Relative to the end of the 32-bit displacement.
+        let rel = (target as i64 - (*at as i64 + 4)) as i32;
+        e.bytes[*at..*at + 4].copy_from_slice(&rel.to_le_bytes());
+    }
+
+    e.bytes
+}
+
+struct Encoder {
+    bytes: Vec<u8>,
+    labels: BTreeMap<&'static str, usize>,
+    /// Positions of 32-bit displacements to patch with a label's offset.
+    fixups: Vec<(usize, &'static str)>,
+}
+
+fn number(r: Register) -> u8 {
+    r as u8
+}
+
+fn fits_i8(i: i64) -> bool {
+    i as i8 as i64 == i
+}
+
+fn fits_i32(i: i64) -> bool {
+    i as i32 as i64 == i
+}
+
+impl Encoder {
+    fn encode_instruction(&mut self, i: &Instruction) {
+        use Operand::{Immediate as Imm, Memory as Mem, Register as Reg};
+
+        match *i {
+            Instruction::Push(Reg(r)) => self.short(0x50, r),
+            Instruction::Push(Imm(i)) if fits_i8(i) => self.bytes.extend(&[0x6a, i as u8]),
+            Instruction::Push(Imm(i)) if fits_i32(i) => {
+                self.bytes.push(0x68);
+                self.bytes.extend(&(i as i32).to_le_bytes());
+            }
+            Instruction::Push(m @ Mem(_)) => self.modrm(false, &[0xff], 6, m),
+            Instruction::Pop(Reg(r)) => self.short(0x58, r),
+            Instruction::Pop(m @ Mem(_)) => self.modrm(false, &[0x8f], 0, m),
+
+            Instruction::Mov(Reg(r), Imm(i)) if fits_i32(i) => {
+                self.modrm(true, &[0xc7], 0, Reg(r));
+                self.imm32(i);
+            }
+            Instruction::Mov(Reg(r), Imm(i)) => {
+                self.rex(true, 0, 0, number(r));
+                self.bytes.push(0xb8 + (number(r) & 7));
+                self.bytes.extend(&i.to_le_bytes());
+            }
+            Instruction::Mov(m @ Mem(_), Imm(i)) if fits_i32(i) => {
+                self.modrm(true, &[0xc7], 0, m);
+                self.imm32(i);
+            }
+            Instruction::Mov(a, Reg(s)) => self.modrm(true, &[0x89], number(s), a),
+            Instruction::Mov(Reg(d), b @ Mem(_)) => self.modrm(true, &[0x8b], number(d), b),
+            Instruction::Movzx(Reg(d), Operand::Byte(s)) => {
+                self.modrm_byte(true, &[0x0f, 0xb6], number(d), s)
+            }
+            Instruction::MovByte(address, s) => {
+                let index = address.index.map_or(0, |(r, _)| number(r));
+                let rex =
+                    0x40 | (number(s) >> 3) << 2 | (index >> 3) << 1 | number(address.base) >> 3;
+                // Without a REX prefix, 4 to 7 mean ah, ch, dh and bh.
+                if rex != 0x40 || (4..8).contains(&number(s)) {
+                    self.bytes.push(rex);
+                }
+                self.bytes.push(0x88);
+                self.address(number(s), &address);
+            }
+            Instruction::Lea(d, address) => self.modrm(true, &[0x8d], number(d), Mem(address)),
+
+            Instruction::Add(a, b) => self.arithmetic(0x01, 0x03, 0, a, b),
+            Instruction::Sub(a, b) => self.arithmetic(0x29, 0x2b, 5, a, b),
+            Instruction::Cmp(a, b) => self.arithmetic(0x39, 0x3b, 7, a, b),
+            Instruction::Imul(Reg(d), b @ (Reg(_) | Mem(_))) => {
+                self.modrm(true, &[0x0f, 0xaf], number(d), b)
+            }
+            Instruction::Shl(a, Imm(i)) if (0..64).contains(&i) => {
+                self.modrm(true, &[0xc1], 4, a);
+                self.bytes.push(i as u8);
+            }
+            Instruction::Sete(Operand::Byte(r)) => self.modrm_byte(false, &[0x0f, 0x94], 0, r),
+            Instruction::Neg(a) => self.modrm(true, &[0xf7], 3, a),
+            Instruction::Not(a) => self.modrm(true, &[0xf7], 2, a),
+            Instruction::Idiv(a) => self.modrm(true, &[0xf7], 7, a),
+            Instruction::Cqo => self.bytes.extend(&[0x48, 0x99]),
+            Instruction::Ret => self.bytes.push(0xc3),
+            Instruction::Syscall => self.bytes.extend(&[0x0f, 0x05]),
+
+            Instruction::Label(label) => {
+                self.labels.insert(label, self.bytes.len());
+            }
+            Instruction::J(condition, label) => {
+                self.relative(&[0x0f, 0x80 | condition.code()], label)
+            }
+            Instruction::Call(label) => self.relative(&[0xe8], label),
+
+            _ => panic!("cannot encode {:?}", i),
+        }
+    }
+
+    /// Two-operand ALU instructions: `mr` takes a register source, `rm` a
+    /// memory source, and `/digit` selects the operation for immediates.
+    fn arithmetic(&mut self, mr: u8, rm: u8, digit: u8, a: Operand, b: Operand) {
+        match (a, b) {
+            (a, Operand::Immediate(i)) if fits_i8(i) => {
+                self.modrm(true, &[0x83], digit, a);
+                self.bytes.push(i as u8);
+            }
+            (a, Operand::Immediate(i)) if fits_i32(i) => {
+                self.modrm(true, &[0x81], digit, a);
+                self.imm32(i);
+            }
+            (a, Operand::Register(s)) => {
+                self.modrm(true, &[mr], number(s), a);
+            }
+            (Operand::Register(d), b @ Operand::Memory(_)) => {
+                self.modrm(true, &[rm], number(d), b);
+            }
+            _ => panic!("cannot encode {:?}, {:?}", a, b),
+        }
+    }
+
+    /// `opcode + r`, as used by push and pop.
+    fn short(&mut self, opcode: u8, r: Register) {
+        if number(r) >= 8 {
+            self.bytes.push(0x41);
+        }
+        self.bytes.push(opcode + (number(r) & 7));
+    }
+
+    fn rex(&mut self, w: bool, reg: u8, index: u8, base: u8) {
+        let rex = 0x40 | (w as u8) << 3 | (reg >> 3) << 2 | (index >> 3) << 1 | base >> 3;
+        if rex != 0x40 {
+            self.bytes.push(rex);
+        }
+    }
+
+    /// An instruction with a ModRM byte whose r/m operand is a register or
+    /// memory, and whose reg field is `reg`.
+    fn modrm(&mut self, w: bool, opcode: &[u8], reg: u8, rm: Operand) {
+        match rm {
+            Operand::Register(r) => {
+                self.rex(w, reg, 0, number(r));
+                self.bytes.extend(opcode);
+                self.bytes.push(0xc0 | (reg & 7) << 3 | number(r) & 7);
+            }
+            Operand::Memory(address) => {
+                let index = address.index.map_or(0, |(r, _)| number(r));
+                self.rex(w, reg, index, number(address.base));
+                self.bytes.extend(opcode);
+                self.address(reg, &address);
+            }
+            _ => panic!("cannot encode {:?} as r/m", rm),
+        }
+    }
+
+    /// Like `modrm` for the low byte of `r`, which needs a REX prefix for
+    /// spl, bpl, sil and dil instead of ah, ch, dh and bh.
+    fn modrm_byte(&mut self, w: bool, opcode: &[u8], reg: u8, r: Register) {
+        if !w && (4..8).contains(&number(r)) {
+            self.bytes.push(0x40);
+        }
+        self.modrm(w, opcode, reg, Operand::Register(r));
+    }
+
+    fn address(&mut self, reg: u8, address: &Address) {
+        let base = number(address.base) & 7;
+        let disp = address.displacement as i64;
+        // rbp and r13 as a base always need a displacement.
+        let (mode, disp_len) = match disp {
+            0 if base != 5 => (0b00, 0),
+            d if fits_i8(d) => (0b01, 1),
+            _ => (0b10, 4),
+        };
+
+        match address.index {
+            Some((index, scale)) => {
+                self.bytes.push(mode << 6 | (reg & 7) << 3 | 0b100);
+                let scale = scale.trailing_zeros() as u8;
+                self.bytes
+                    .push(scale << 6 | (number(index) & 7) << 3 | base);
+            }
+            // rsp and r12 as a base need a SIB byte without an index.
+            None if base == 4 => {
+                self.bytes.push(mode << 6 | (reg & 7) << 3 | 0b100);
+                self.bytes.push(0x24);
+            }
+            None => self.bytes.push(mode << 6 | (reg & 7) << 3 | base),
+        }
+
+        match disp_len {
+            1 => self.bytes.push(disp as u8),
+            4 => self.bytes.extend(&(disp as i32).to_le_bytes()),
+            _ => {}
+        }
+    }
+
+    fn imm32(&mut self, i: i64) {
+        self.bytes.extend(&(i as i32).to_le_bytes());
+    }
+
+    fn relative(&mut self, opcode: &[u8], label: &'static str) {
+        self.bytes.extend(opcode);
+        self.fixups.push((self.bytes.len(), label));
+        self.bytes.extend(&[0; 4]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::encoder::encode;
+    use crate::compiler::x86::Instruction::*;
+    use crate::compiler::x86::Operand::*;
+    use crate::compiler::x86::Register::*;
+    use crate::compiler::x86::{Address, Condition};
+
+    #[test]
+    fn test_encode() {
+        let tests = vec![
+            (vec![0x6a, 0x01], Push(Immediate(1))),
+            (vec![0x68, 0x80, 0x00, 0x00, 0x00], Push(Immediate(128))),
+            (vec![0x41, 0x57], Push(Register(R15))),
+            (vec![0x5f], Pop(Register(Rdi))),
+            (
+                vec![0x48, 0xc7, 0xc1, 0xff, 0xff, 0xff, 0xff],
+                Mov(Register(Rcx), Immediate(-1)),
+            ),
+            (
+                vec![0x49, 0xbb, 0, 0, 0, 0, 0, 0, 0, 0x80],
+                Mov(Register(R11), Immediate(i64::MIN)),
+            ),
+            (
+                vec![0x48, 0x89, 0x4d, 0xf8],
+                Mov(Memory(Address::new(Rbp, -8)), Register(Rcx)),
+            ),
+            (
+                vec![0x4c, 0x8b, 0x04, 0x24],
+                Mov(Register(R8), Memory(Address::new(Rsp, 0))),
+            ),
+            (
+                vec![0x49, 0x8b, 0x45, 0x00],
+                Mov(Register(Rax), Memory(Address::new(R13, 0))),
+            ),
+            (
+                vec![0x48, 0x0f, 0xb6, 0xf6],
+                Movzx(Register(Rsi), Byte(Rsi)),
+            ),
+            (vec![0x40, 0x0f, 0x94, 0xc6], Sete(Byte(Rsi))),
+            (vec![0x41, 0x0f, 0x94, 0xc0], Sete(Byte(R8))),
+            (
+                vec![0x48, 0x8d, 0x04, 0x80],
+                Lea(
+                    Rax,
+                    Address {
+                        base: Rax,
+                        index: Some((Rax, 4)),
+                        displacement: 0,
+                    },
+                ),
+            ),
+            (vec![0x48, 0x01, 0xf8], Add(Register(Rax), Register(Rdi))),
+            (
+                vec![0x48, 0x81, 0xec, 0xc8, 0x00, 0x00, 0x00],
+                Sub(Register(Rsp), Immediate(200)),
+            ),
+            (
+                vec![0x48, 0x83, 0xff, 0x00],
+                Cmp(Register(Rdi), Immediate(0)),
+            ),
+            (
+                vec![0x49, 0x0f, 0xaf, 0xc1],
+                Imul(Register(Rax), Register(R9)),
+            ),
+            (
+                vec![0x48, 0xc1, 0xe0, 0x03],
+                Shl(Register(Rax), Immediate(3)),
+            ),
+            (vec![0x48, 0xf7, 0xd8], Neg(Register(Rax))),
+            (vec![0x48, 0xf7, 0xd0], Not(Register(Rax))),
+            (
+                vec![0x48, 0xf7, 0x7d, 0x10],
+                Idiv(Memory(Address::new(Rbp, 16))),
+            ),
+            (vec![0x48, 0x99], Cqo),
+            (vec![0xc3], Ret),
+            (vec![0x40, 0x88, 0x3e], MovByte(Address::new(Rsi, 0), Rdi)),
+            (vec![0x88, 0x56, 0xff], MovByte(Address::new(Rsi, -1), Rdx)),
+            (
+                vec![0x45, 0x88, 0x0c, 0x24],
+                MovByte(Address::new(R12, 0), R9),
+            ),
+            (vec![0x0f, 0x05], Syscall),
+        ];
+
+        for (want, i) in tests {
+            assert_eq!(want, encode(&[i]));
+        }
+    }
+
+    #[test]
+    fn test_encode_labels() {
+        let code = vec![
+            Call("body"),
+            Ret,
+            Label("divide_by_zero"),
+            Ret,
+            Label("body"),
+            Cmp(Register(Rdi), Immediate(0)),
+            J(Condition::E, "divide_by_zero"),
+            J(Condition::Ge, "body"),
+            Ret,
+        ];
+        let want = vec![
+            0xe8, 0x02, 0x00, 0x00, 0x00, // call body
+            0xc3, // ret
+            0xc3, // divide_by_zero: ret
+            0x48, 0x83, 0xff, 0x00, // body: cmp rdi, 0
+            0x0f, 0x84, 0xf5, 0xff, 0xff, 0xff, // je divide_by_zero
+            0x0f, 0x8d, 0xf0, 0xff, 0xff, 0xff, // jge body
+            0xc3, // ret
+        ];
+
+        assert_eq!(want, encode(&code));
+    }
+}
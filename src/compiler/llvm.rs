@@ -0,0 +1,274 @@
+use crate::compiler::EXPRESSION;
+use crate::ir::{BinaryOp, Function, Instruction, UnaryOp, Value};
+use std::collections::BTreeMap;
+
+const DECLARATIONS: &str = r#"declare { i64, i1 } @llvm.sadd.with.overflow.i64(i64, i64)
+declare { i64, i1 } @llvm.ssub.with.overflow.i64(i64, i64)
+declare { i64, i1 } @llvm.smul.with.overflow.i64(i64, i64)
+declare void @llvm.trap()
+"#;
+
+const MAIN: &str = r#"
+@format = private unnamed_addr constant [5 x i8] c"%ld\0A\00"
+
+declare i32 @printf(i8*, ...)
+
+define i32 @main() {
+entry:
+  %value = call i64 @{expression}()
+  %format = getelementptr [5 x i8], [5 x i8]* @format, i64 0, i64 0
+  call i32 (i8*, ...) @printf(i8* %format, i64 %value)
+  ret i32 0
+}
+"#;
+
+const EXIT_CODE_MAIN: &str = r#"
+define i32 @main() {
+entry:
+  %value = call i64 @{expression}()
+  %status = trunc i64 %value to i32
+  ret i32 %status
+}
+"#;
+
+/// Prints `f` as an LLVM module defining `i64 @<name>()`. If `main` is set,
+/// the function is named `EXPRESSION` instead and an `i32 @main()` prints
+/// its result. A function named `main` is named `EXPRESSION` too, and an
+/// `i32 @main()` returns its result truncated, since `main` has to return
+/// an `i32`.
+///
+/// Arithmetic is checked: an overflow, a division by zero or the
+/// overflowing division of `i64::MIN` by -1 calls `llvm.trap`.
+pub fn print(f: &Function, main: bool) -> String {
+    let mut p = Printer {
+        operands: BTreeMap::new(),
+        body: String::new(),
+        checked: false,
+    };
+
+    for i in &f.instructions {
+        p.print_instruction(i);
+    }
+
+    let mut code = DECLARATIONS.to_string();
+    let name = match main || f.name == "main" {
+        true => EXPRESSION,
+        false => &f.name,
+    };
+    code.push_str(&format!("\ndefine i64 @{}() {{\nentry:\n", name));
+    code.push_str(&p.body);
+    code.push_str(&format!("  ret i64 {}\n", p.operand(f.result)));
+    if p.checked {
+        code.push_str("\ntrap:\n  call void @llvm.trap()\n  unreachable\n");
+    }
+    code.push_str("}\n");
+
+    match (main, name == EXPRESSION) {
+        (true, _) => code.push_str(&MAIN.replace("{expression}", EXPRESSION)),
+        (false, true) => code.push_str(&EXIT_CODE_MAIN.replace("{expression}", EXPRESSION)),
+        (false, false) => {}
+    }
+
+    code
+}
+
+struct Printer {
+    /// How each value is spelled as an operand. Constants are inlined.
+    operands: BTreeMap<Value, String>,
+    body: String,
+    /// Whether any instruction branches to the trap block.
+    checked: bool,
+}
+
+impl Printer {
+    fn operand(&self, v: Value) -> &str {
+        &self.operands[&v]
+    }
+
+    fn emit(&mut self, line: String) {
+        self.body.push_str("  ");
+        self.body.push_str(&line);
+        self.body.push('\n');
+    }
+
+    fn print_instruction(&mut self, i: &Instruction) {
+        let d = i.defines();
+        let name = format!("%v{}", d.0);
+
+        let operand = match *i {
+            Instruction::Const(_, n) => n.to_string(),
+            Instruction::Unary(_, UnaryOp::Neg, a) => {
+                let a = self.operand(a).to_string();
+                self.print_overflow(&name, "ssub", "0", &a);
+                name
+            }
+            Instruction::Unary(_, UnaryOp::Not, a) => {
+                let line = format!("{} = xor i64 {}, -1", name, self.operand(a));
+                self.emit(line);
+                name
+            }
+            Instruction::Unary(_, UnaryOp::LogicalNot, a) => {
+                let line = format!("{}.zero = icmp eq i64 {}, 0", name, self.operand(a));
+                self.emit(line);
+                self.emit(format!("{} = zext i1 {}.zero to i64", name, name));
+                name
+            }
+            Instruction::Binary(_, BinaryOp::Div, a, b) => {
+                let (a, b) = (self.operand(a).to_string(), self.operand(b).to_string());
+                self.emit(format!("{}.zero = icmp eq i64 {}, 0", name, b));
+                self.emit(format!("{}.min = icmp eq i64 {}, {}", name, a, i64::MIN));
+                self.emit(format!("{}.minus = icmp eq i64 {}, -1", name, b));
+                self.emit(format!(
+                    "{}.overflow = and i1 {}.min, {}.minus",
+                    name, name, name
+                ));
+                self.emit(format!(
+                    "{}.invalid = or i1 {}.zero, {}.overflow",
+                    name, name, name
+                ));
+                self.print_check(&name, &format!("{}.invalid", name));
+                self.emit(format!("{} = sdiv i64 {}, {}", name, a, b));
+                name
+            }
+            Instruction::Binary(_, op, a, b) => {
+                let intrinsic = match op {
+                    BinaryOp::Add => "sadd",
+                    BinaryOp::Sub => "ssub",
+                    _ => "smul",
+                };
+                let (a, b) = (self.operand(a).to_string(), self.operand(b).to_string());
+                self.print_overflow(&name, intrinsic, &a, &b);
+                name
+            }
+        };
+
+        self.operands.insert(d, operand);
+    }
+
+    /// `name = a op b` through `llvm.<intrinsic>.with.overflow`.
+    fn print_overflow(&mut self, name: &str, intrinsic: &str, a: &str, b: &str) {
+        self.emit(format!(
+            "{}.result = call {{ i64, i1 }} @llvm.{}.with.overflow.i64(i64 {}, i64 {})",
+            name, intrinsic, a, b
+        ));
+        self.emit(format!(
+            "{} = extractvalue {{ i64, i1 }} {}.result, 0",
+            name, name
+        ));
+        self.emit(format!(
+            "{}.overflow = extractvalue {{ i64, i1 }} {}.result, 1",
+            name, name
+        ));
+        self.print_check(name, &format!("{}.overflow", name));
+    }
+
+    /// Branches to the trap block if `condition` holds.
+    fn print_check(&mut self, name: &str, condition: &str) {
+        self.emit(format!(
+            "br i1 {}, label %trap, label {}.ok",
+            condition, name
+        ));
+        self.body.push_str(&format!("\n{}.ok:\n", &name[1..]));
+        self.checked = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::Statement;
+    use crate::compiler::llvm::print;
+    use crate::ir::{lower, Function};
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Statement {
+        Parser::new(Lexer::new(input)).parse().unwrap().remove(0)
+    }
+
+    fn formula(input: &str) -> Function {
+        let mut f = lower(&parse(input));
+        f.name = "formula".to_string();
+        f
+    }
+
+    #[test]
+    fn test_print() {
+        let want = r#"
+define i64 @formula() {
+entry:
+  %v2.result = call { i64, i1 } @llvm.sadd.with.overflow.i64(i64 1, i64 2)
+  %v2 = extractvalue { i64, i1 } %v2.result, 0
+  %v2.overflow = extractvalue { i64, i1 } %v2.result, 1
+  br i1 %v2.overflow, label %trap, label %v2.ok
+
+v2.ok:
+  %v3 = xor i64 %v2, -1
+  ret i64 %v3
+
+trap:
+  call void @llvm.trap()
+  unreachable
+}
+"#;
+
+        assert!(print(&formula("~(1+2)"), false).ends_with(want));
+    }
+
+    #[test]
+    fn test_print_unchecked() {
+        let want = r#"
+define i64 @formula() {
+entry:
+  %v1.zero = icmp eq i64 7, 0
+  %v1 = zext i1 %v1.zero to i64
+  ret i64 %v1
+}
+"#;
+
+        assert!(print(&formula("!7"), false).ends_with(want));
+        assert!(print(&formula("5"), false).ends_with("entry:\n  ret i64 5\n}\n"));
+    }
+
+    #[test]
+    fn test_print_div() {
+        let code = print(&formula("8/2"), false);
+        let want = vec![
+            "  %v2.zero = icmp eq i64 2, 0",
+            "  %v2.min = icmp eq i64 8, -9223372036854775808",
+            "  %v2.minus = icmp eq i64 2, -1",
+            "  %v2.overflow = and i1 %v2.min, %v2.minus",
+            "  %v2.invalid = or i1 %v2.zero, %v2.overflow",
+            "  br i1 %v2.invalid, label %trap, label %v2.ok",
+            "",
+            "v2.ok:",
+            "  %v2 = sdiv i64 8, 2",
+        ];
+
+        let got: Vec<&str> = code
+            .lines()
+            .skip_while(|l| *l != "entry:")
+            .skip(1)
+            .take(want.len())
+            .collect();
+        assert_eq!(want, got);
+    }
+
+    #[test]
+    fn test_print_main() {
+        let code = print(&lower(&parse("5")), true);
+
+        assert!(code.contains("\ndefine i64 @calc_expr() {\nentry:\n  ret i64 5\n}\n"));
+        assert!(code.contains("\ndefine i32 @main() {\n"));
+        assert!(code.contains("  %value = call i64 @calc_expr()\n"));
+    }
+
+    #[test]
+    fn test_print_exit_code() {
+        let code = print(&lower(&parse("5")), false);
+
+        assert!(code.contains("\ndefine i64 @calc_expr() {\nentry:\n  ret i64 5\n}\n"));
+        assert!(code.contains("\ndefine i32 @main() {\n"));
+        assert!(code.contains("  %status = trunc i64 %value to i32\n  ret i32 %status\n"));
+        assert!(!code.contains("@printf"));
+    }
+}
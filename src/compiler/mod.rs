@@ -0,0 +1,1068 @@
+pub mod aarch64;
+pub mod c;
+pub mod elf;
+pub mod encoder;
+pub mod llvm;
+pub mod peephole;
+pub mod printer;
+pub mod riscv64;
+pub mod wasm;
+pub mod x86;
+
+use crate::ast::Statement;
+use crate::error::RuntimeError;
+use crate::ir::*;
+use printer::Syntax;
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use x86::{Address, Assembly, Condition, Instruction as Asm, Operand, Register};
+
+/// Where intermediate values live while an expression is computed.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Allocation {
+    /// Every intermediate is pushed to and popped from the stack.
+    Stack,
+    /// Intermediates are kept in registers by a linear scan over the IR,
+    /// and spilled to the stack frame only when they run out.
+    Registers,
+}
+
+/// Architecture to generate code for. Every target runs on Linux.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Target {
+    X86_64,
+    Aarch64,
+    Riscv64,
+}
+
+/// The label checked divisions jump to when the divisor is 0. Whoever
+/// enables `check_division` has to define it and `OVERFLOW`.
+pub const DIVIDE_BY_ZERO: &str = "divide_by_zero";
+
+/// The label checked arithmetic jumps to when the result does not fit in 64
+/// bits. Whoever enables `check_division` or `check_overflow` has to define
+/// it.
+pub const OVERFLOW: &str = "overflow";
+
+/// The exit status of a program stopped by a runtime error, like
+/// `EXIT_FAILURE` in the C backend.
+pub const TRAP_STATUS: i64 = 1;
+
+/// The function `main` calls to compute the result when `print_result` is
+/// set, and the one the wrapper calls when `return_errors` is.
+pub const EXPRESSION: &str = "calc_expr";
+
+/// What a function generated with `return_errors` returns in rdx when it
+/// divides by 0.
+pub const RETURNED_DIVIDE_BY_ZERO: i64 = 1;
+
+/// What a function generated with `return_errors` returns in rdx when the
+/// result does not fit in 64 bits.
+pub const RETURNED_OVERFLOW: i64 = 2;
+
+const SYS_WRITE: i64 = 1;
+const SYS_EXIT: i64 = 60;
+const STDOUT: i64 = 1;
+const STDERR: i64 = 2;
+
+// rax and rdx are left out because idiv clobbers them.
+const REGISTERS: [Register; 7] = [
+    Register::Rcx,
+    Register::Rsi,
+    Register::Rdi,
+    Register::R8,
+    Register::R9,
+    Register::R10,
+    Register::R11,
+];
+
+#[derive(Clone, Debug)]
+pub struct Compiler {
+    allocation: Allocation,
+    peephole: bool,
+    syntax: Syntax,
+    target: Target,
+    check_division: bool,
+    check_overflow: bool,
+    define_traps: bool,
+    return_errors: bool,
+    print_result: bool,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Self {
+            allocation: Allocation::Stack,
+            peephole: false,
+            syntax: Syntax::Intel,
+            target: Target::X86_64,
+            check_division: false,
+            check_overflow: false,
+            define_traps: false,
+            return_errors: false,
+            print_result: false,
+        }
+    }
+
+    pub fn allocation(mut self, allocation: Allocation) -> Self {
+        self.allocation = allocation;
+        self
+    }
+
+    /// Runs the peephole optimizer over the generated instructions.
+    pub fn peephole(mut self, peephole: bool) -> Self {
+        self.peephole = peephole;
+        self
+    }
+
+    pub fn syntax(mut self, syntax: Syntax) -> Self {
+        self.syntax = syntax;
+        self
+    }
+
+    /// Allocation, peephole and syntax only apply to x86-64.
+    pub fn target(mut self, target: Target) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Jumps to `DIVIDE_BY_ZERO` before dividing by 0 and to `OVERFLOW`
+    /// before dividing `i64::MIN` by -1 on x86-64, where `idiv` would raise
+    /// SIGFPE for either.
+    pub fn check_division(mut self, check_division: bool) -> Self {
+        self.check_division = check_division;
+        self
+    }
+
+    /// Jumps to `OVERFLOW` when addition, subtraction, multiplication or
+    /// negation overflows on x86-64, instead of wrapping around.
+    pub fn check_overflow(mut self, check_overflow: bool) -> Self {
+        self.check_overflow = check_overflow;
+        self
+    }
+
+    /// Defines `DIVIDE_BY_ZERO` and `OVERFLOW` after the function. They
+    /// write the evaluator's message to stderr and exit with `TRAP_STATUS`.
+    pub fn define_traps(mut self, define_traps: bool) -> Self {
+        self.define_traps = define_traps;
+        self
+    }
+
+    /// Returns runtime errors to the caller instead of stopping the program.
+    /// The function returns the result in rax and 0 in rdx, or else
+    /// `RETURNED_DIVIDE_BY_ZERO` or `RETURNED_OVERFLOW` in rdx, which is how
+    /// a C function returns a struct of two `int64_t`. It defines
+    /// `DIVIDE_BY_ZERO` and `OVERFLOW` itself, so it takes the place of
+    /// `define_traps`. Ignored with `print_result`.
+    pub fn return_errors(mut self, return_errors: bool) -> Self {
+        self.return_errors = return_errors;
+        self
+    }
+
+    /// Generates a `main` that prints the result to stdout and exits with
+    /// 0, instead of returning the result as the exit status.
+    pub fn print_result(mut self, print_result: bool) -> Self {
+        self.print_result = print_result;
+        self
+    }
+
+    pub fn compile(&self, s: Statement) -> String {
+        self.compile_function(&lower(&s))
+    }
+
+    pub fn compile_function(&self, f: &Function) -> String {
+        match self.target {
+            Target::X86_64 => printer::print(&self.generate(f), self.syntax),
+            Target::Aarch64 if self.print_result => aarch64::print_program(&aarch64::generate(f)),
+            Target::Aarch64 => aarch64::print(&f.name, &aarch64::generate(f)),
+            Target::Riscv64 if self.print_result => riscv64::print_program(&riscv64::generate(f)),
+            Target::Riscv64 => riscv64::print(&f.name, &riscv64::generate(f)),
+        }
+    }
+
+    pub fn generate(&self, f: &Function) -> Assembly {
+        let mut code = match self.allocation {
+            Allocation::Stack => self.generate_stack(f),
+            Allocation::Registers => RegisterAllocator::new(f, self.checks()).generate(f),
+        };
+        code.push(Asm::Ret);
+
+        if self.peephole {
+            code = peephole::optimize(code);
+        }
+
+        let symbol = match (self.print_result, self.return_errors) {
+            (true, _) => {
+                code = generate_print(code);
+                "main".to_string()
+            }
+            (false, true) => {
+                code = generate_return_errors(code);
+                f.name.clone()
+            }
+            (false, false) => f.name.clone(),
+        };
+
+        if self.define_traps {
+            code.extend(generate_traps());
+        }
+
+        Assembly {
+            symbol,
+            instructions: code,
+        }
+    }
+
+    fn checks(&self) -> Checks {
+        Checks {
+            division: self.check_division,
+            overflow: self.check_overflow,
+        }
+    }
+
+    fn generate_stack(&self, f: &Function) -> Vec<Asm> {
+        let rax = Operand::Register(Register::Rax);
+        let rdi = Operand::Register(Register::Rdi);
+        let mut code = vec![];
+
+        for i in &f.instructions {
+            match *i {
+                Instruction::Const(_, i) if i as i32 as i64 != i => {
+                    // push only takes a sign-extended 32-bit immediate.
+                    code.push(Asm::Mov(rax, Operand::Immediate(i)));
+                    code.push(Asm::Push(rax));
+                }
+                Instruction::Const(_, i) => code.push(Asm::Push(Operand::Immediate(i))),
+                Instruction::Unary(_, op, _) => {
+                    code.push(Asm::Pop(rax));
+                    generate_unary(op, Register::Rax, self.checks(), &mut code);
+                    code.push(Asm::Push(rax));
+                }
+                Instruction::Binary(_, op, a, b) => {
+                    // The operand computed last is on top of the stack.
+                    match a < b {
+                        true => code.extend(vec![Asm::Pop(rdi), Asm::Pop(rax)]),
+                        false => code.extend(vec![Asm::Pop(rax), Asm::Pop(rdi)]),
+                    }
+                    generate_binary(op, Register::Rax, rdi, self.checks(), &mut code);
+                    code.push(Asm::Push(rax));
+                }
+            }
+        }
+
+        code.push(Asm::Pop(rax));
+        code
+    }
+}
+
+/// Wraps `body` in a `main` that writes its result in decimal to stdout
+/// with the `write` system call, so it needs no C library.
+///
+/// Digits are produced from the end of a buffer on the stack. The value is
+/// made non-positive first, because `i64::MIN` has no positive counterpart;
+/// the remainders of dividing it by 10 are then between -9 and 0.
+fn generate_print(body: Vec<Asm>) -> Vec<Asm> {
+    let rax = Operand::Register(Register::Rax);
+    let rcx = Operand::Register(Register::Rcx);
+    let rdx = Operand::Register(Register::Rdx);
+    let rdi = Operand::Register(Register::Rdi);
+    let rsi = Operand::Register(Register::Rsi);
+    let rsp = Operand::Register(Register::Rsp);
+    let r8 = Operand::Register(Register::R8);
+    let cursor = Address::new(Register::Rsi, 0);
+    let end = Address::new(Register::Rsp, 32);
+
+    let mut code = vec![
+        Asm::Call(EXPRESSION),
+        Asm::Sub(rsp, Operand::Immediate(32)),
+        Asm::Lea(Register::Rsi, end),
+        Asm::Sub(rsi, Operand::Immediate(1)),
+        Asm::Mov(rdx, Operand::Immediate(b'\n' as i64)),
+        Asm::MovByte(cursor, Register::Rdx),
+        Asm::Mov(r8, rax),
+        Asm::Cmp(rax, Operand::Immediate(0)),
+        Asm::J(Condition::Le, "print_digit"),
+        Asm::Neg(rax),
+        Asm::Label("print_digit"),
+        Asm::Mov(rcx, Operand::Immediate(10)),
+        Asm::Cqo,
+        Asm::Idiv(rcx),
+        Asm::Mov(rdi, Operand::Immediate(b'0' as i64)),
+        Asm::Sub(rdi, rdx),
+        Asm::Sub(rsi, Operand::Immediate(1)),
+        Asm::MovByte(cursor, Register::Rdi),
+        Asm::Cmp(rax, Operand::Immediate(0)),
+        Asm::J(Condition::Ne, "print_digit"),
+        Asm::Cmp(r8, Operand::Immediate(0)),
+        Asm::J(Condition::Ge, "print_write"),
+        Asm::Mov(rdi, Operand::Immediate(b'-' as i64)),
+        Asm::Sub(rsi, Operand::Immediate(1)),
+        Asm::MovByte(cursor, Register::Rdi),
+        Asm::Label("print_write"),
+        Asm::Mov(rax, Operand::Immediate(SYS_WRITE)),
+        Asm::Mov(rdi, Operand::Immediate(STDOUT)),
+        Asm::Lea(Register::Rdx, end),
+        Asm::Sub(rdx, rsi),
+        Asm::Syscall,
+        Asm::Add(rsp, Operand::Immediate(32)),
+        Asm::Mov(rax, Operand::Immediate(0)),
+        Asm::Ret,
+        Asm::Label(EXPRESSION),
+    ];
+    code.extend(body);
+    code
+}
+
+/// Wraps `body` in a function that saves the stack pointer in rbx before
+/// calling it. A runtime error jumps out of `body` from wherever it is,
+/// restores the stack pointer and returns the error in rdx, so the caller's
+/// stack stays intact. rbx and rbp are saved too, since callers expect them
+/// kept.
+fn generate_return_errors(body: Vec<Asm>) -> Vec<Asm> {
+    let rbx = Operand::Register(Register::Rbx);
+    let rbp = Operand::Register(Register::Rbp);
+    let rdx = Operand::Register(Register::Rdx);
+    let rsp = Operand::Register(Register::Rsp);
+
+    let mut code = vec![
+        Asm::Push(rbx),
+        Asm::Push(rbp),
+        Asm::Mov(rbx, rsp),
+        Asm::Call(EXPRESSION),
+        Asm::Mov(rdx, Operand::Immediate(0)),
+        Asm::Pop(rbp),
+        Asm::Pop(rbx),
+        Asm::Ret,
+    ];
+    for (label, error) in [
+        (DIVIDE_BY_ZERO, RETURNED_DIVIDE_BY_ZERO),
+        (OVERFLOW, RETURNED_OVERFLOW),
+    ] {
+        code.extend(vec![
+            Asm::Label(label),
+            Asm::Mov(rsp, rbx),
+            Asm::Mov(rdx, Operand::Immediate(error)),
+            Asm::Pop(rbp),
+            Asm::Pop(rbx),
+            Asm::Ret,
+        ]);
+    }
+    code.push(Asm::Label(EXPRESSION));
+    code.extend(body);
+    code
+}
+
+/// Handlers for the labels checked code jumps to. They never return, so
+/// the message is pushed onto whatever stack is current, 8 bytes at a time
+/// from its end.
+fn generate_traps() -> Vec<Asm> {
+    let rax = Operand::Register(Register::Rax);
+    let rdi = Operand::Register(Register::Rdi);
+    let rsi = Operand::Register(Register::Rsi);
+    let rdx = Operand::Register(Register::Rdx);
+    let rsp = Operand::Register(Register::Rsp);
+    let traps = [
+        (DIVIDE_BY_ZERO, RuntimeError::DivideByZero),
+        (OVERFLOW, RuntimeError::Overflow),
+    ];
+
+    let mut code = vec![];
+    for (label, error) in traps {
+        let mut message = format!("{}\n", error).into_bytes();
+        let len = message.len();
+        message.resize(len.div_ceil(8) * 8, 0);
+
+        code.push(Asm::Label(label));
+        for chunk in message.chunks(8).rev() {
+            let chunk = i64::from_le_bytes(chunk.try_into().unwrap());
+            code.push(Asm::Mov(rax, Operand::Immediate(chunk)));
+            code.push(Asm::Push(rax));
+        }
+        code.extend(vec![
+            Asm::Mov(rax, Operand::Immediate(SYS_WRITE)),
+            Asm::Mov(rdi, Operand::Immediate(STDERR)),
+            Asm::Mov(rsi, rsp),
+            Asm::Mov(rdx, Operand::Immediate(len as i64)),
+            Asm::Syscall,
+            Asm::Mov(rax, Operand::Immediate(SYS_EXIT)),
+            Asm::Mov(rdi, Operand::Immediate(TRAP_STATUS)),
+            Asm::Syscall,
+        ]);
+    }
+    code
+}
+
+/// Which runtime errors generated code jumps to a trap for.
+#[derive(Clone, Copy, Debug)]
+struct Checks {
+    division: bool,
+    overflow: bool,
+}
+
+fn generate_unary(op: UnaryOp, r: Register, checks: Checks, code: &mut Vec<Asm>) {
+    match op {
+        UnaryOp::Neg => {
+            code.push(Asm::Neg(Operand::Register(r)));
+            if checks.overflow {
+                code.push(Asm::J(Condition::O, OVERFLOW));
+            }
+        }
+        UnaryOp::Not => code.push(Asm::Not(Operand::Register(r))),
+        UnaryOp::LogicalNot => {
+            code.push(Asm::Cmp(Operand::Register(r), Operand::Immediate(0)));
+            code.push(Asm::Sete(Operand::Byte(r)));
+            code.push(Asm::Movzx(Operand::Register(r), Operand::Byte(r)));
+        }
+    }
+}
+
+/// Computes `r = r op s`, where `s` is a register or a memory operand.
+fn generate_binary(op: BinaryOp, r: Register, s: Operand, checks: Checks, code: &mut Vec<Asm>) {
+    let rax = Operand::Register(Register::Rax);
+    let rdx = Operand::Register(Register::Rdx);
+
+    match op {
+        BinaryOp::Add => code.push(Asm::Add(Operand::Register(r), s)),
+        BinaryOp::Sub => code.push(Asm::Sub(Operand::Register(r), s)),
+        BinaryOp::Mul => code.push(Asm::Imul(Operand::Register(r), s)),
+        BinaryOp::Div => {
+            if checks.division {
+                code.push(Asm::Cmp(s, Operand::Immediate(0)));
+                code.push(Asm::J(Condition::E, DIVIDE_BY_ZERO));
+            }
+            if r != Register::Rax {
+                code.push(Asm::Mov(rax, Operand::Register(r)));
+            }
+            if checks.division {
+                // rdx = s == -1, so rax - rdx overflows exactly for
+                // i64::MIN / -1. idiv clobbers rdx anyway.
+                code.push(Asm::Cmp(s, Operand::Immediate(-1)));
+                code.push(Asm::Sete(Operand::Byte(Register::Rdx)));
+                code.push(Asm::Movzx(rdx, Operand::Byte(Register::Rdx)));
+                code.push(Asm::Cmp(rax, rdx));
+                code.push(Asm::J(Condition::O, OVERFLOW));
+            }
+            code.push(Asm::Cqo);
+            code.push(Asm::Idiv(s));
+            if r != Register::Rax {
+                code.push(Asm::Mov(Operand::Register(r), rax));
+            }
+            return;
+        }
+    }
+
+    if checks.overflow {
+        code.push(Asm::J(Condition::O, OVERFLOW));
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Location {
+    Register(Register),
+    Slot(usize),
+}
+
+impl Location {
+    fn operand(&self) -> Operand {
+        match *self {
+            Location::Register(r) => Operand::Register(r),
+            Location::Slot(n) => Operand::Memory(Address::new(Register::Rbp, -8 * (n as i32 + 1))),
+        }
+    }
+}
+
+/// Linear scan over the instructions of a function. When every register is
+/// taken, the live value whose use is furthest away is spilled to a slot in
+/// the stack frame.
+struct RegisterAllocator {
+    last_use: BTreeMap<Value, usize>,
+    location: BTreeMap<Value, Location>,
+    free: Vec<Register>,
+    slots: usize,
+    body: Vec<Asm>,
+    checks: Checks,
+}
+
+impl RegisterAllocator {
+    fn new(f: &Function, checks: Checks) -> Self {
+        let mut last_use = BTreeMap::new();
+        for (n, i) in f.instructions.iter().enumerate() {
+            for v in i.uses() {
+                last_use.insert(v, n);
+            }
+        }
+        last_use.insert(f.result, f.instructions.len());
+
+        Self {
+            last_use,
+            location: BTreeMap::new(),
+            free: REGISTERS.iter().rev().cloned().collect(),
+            slots: 0,
+            body: vec![],
+            checks,
+        }
+    }
+
+    fn generate(mut self, f: &Function) -> Vec<Asm> {
+        for i in &f.instructions {
+            match *i {
+                Instruction::Const(d, i) => {
+                    let r = self.allocate();
+                    self.body
+                        .push(Asm::Mov(Operand::Register(r), Operand::Immediate(i)));
+                    self.location.insert(d, Location::Register(r));
+                }
+                Instruction::Unary(d, op, a) => {
+                    let r = self.load(a);
+                    generate_unary(op, r, self.checks, &mut self.body);
+                    self.location.insert(d, Location::Register(r));
+                }
+                Instruction::Binary(d, op, a, b) => {
+                    let r = self.load(a);
+                    let s = self.location.remove(&b).unwrap();
+                    generate_binary(op, r, s.operand(), self.checks, &mut self.body);
+                    if let Location::Register(s) = s {
+                        self.free.push(s);
+                    }
+                    self.location.insert(d, Location::Register(r));
+                }
+            }
+        }
+
+        let rbp = Operand::Register(Register::Rbp);
+        let rsp = Operand::Register(Register::Rsp);
+        let mut code = vec![];
+
+        if self.slots > 0 {
+            code.push(Asm::Push(rbp));
+            code.push(Asm::Mov(rbp, rsp));
+            code.push(Asm::Sub(rsp, Operand::Immediate(8 * self.slots as i64)));
+        }
+
+        code.append(&mut self.body);
+        let result = self.location[&f.result];
+        code.push(Asm::Mov(Operand::Register(Register::Rax), result.operand()));
+
+        if self.slots > 0 {
+            code.push(Asm::Mov(rsp, rbp));
+            code.push(Asm::Pop(rbp));
+        }
+
+        code
+    }
+
+    /// Takes `v` out of the allocation, moving it into a register first if
+    /// it was spilled.
+    fn load(&mut self, v: Value) -> Register {
+        match self.location.remove(&v).unwrap() {
+            Location::Register(r) => r,
+            slot => {
+                let r = self.allocate();
+                self.body
+                    .push(Asm::Mov(Operand::Register(r), slot.operand()));
+                r
+            }
+        }
+    }
+
+    fn allocate(&mut self) -> Register {
+        if let Some(r) = self.free.pop() {
+            return r;
+        }
+
+        let victim = self
+            .location
+            .iter()
+            .filter(|(_, l)| matches!(l, Location::Register(_)))
+            .max_by_key(|(v, _)| self.last_use[v])
+            .map(|(v, _)| *v)
+            .unwrap();
+
+        let r = match self.location[&victim] {
+            Location::Register(r) => r,
+            _ => unreachable!("allocate"),
+        };
+        let slot = Location::Slot(self.slots);
+        self.slots += 1;
+
+        self.body
+            .push(Asm::Mov(slot.operand(), Operand::Register(r)));
+        self.location.insert(victim, slot);
+        r
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{Expr, Infix, Literal, Prefix, Statement};
+    use crate::compiler::x86::Instruction::*;
+    use crate::compiler::x86::Operand::*;
+    use crate::compiler::x86::Register::*;
+    use crate::compiler::x86::{Address, Condition, Instruction};
+    use crate::compiler::{Allocation, Compiler, DIVIDE_BY_ZERO, EXPRESSION, OVERFLOW};
+    use crate::ir::lower;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Statement {
+        Parser::new(Lexer::new(input)).parse().unwrap().remove(0)
+    }
+
+    fn generate(c: &Compiler, s: Statement) -> Vec<Instruction> {
+        c.generate(&lower(&s)).instructions
+    }
+
+    #[test]
+    fn test_compile() {
+        let want = r#".intel_syntax noprefix
+.global main
+main:
+  push 1
+  push 1
+  pop rdi
+  pop rax
+  add rax, rdi
+  push rax
+  pop rax
+  ret
+"#;
+
+        assert_eq!(want, Compiler::new().compile(parse("1+1")));
+    }
+
+    #[test]
+    fn test_generate() {
+        let tests = vec![
+            (
+                vec![
+                    Push(Immediate(1)),
+                    Push(Immediate(1)),
+                    Pop(Register(Rdi)),
+                    Pop(Register(Rax)),
+                    Add(Register(Rax), Register(Rdi)),
+                    Push(Register(Rax)),
+                    Pop(Register(Rax)),
+                    Ret,
+                ],
+                Statement::Expression(Expr::Infix(
+                    Infix::Plus,
+                    Box::new(Expr::Literal(Literal::Int(1))),
+                    Box::new(Expr::Literal(Literal::Int(1))),
+                )),
+            ),
+            (
+                vec![
+                    Push(Immediate(1)),
+                    Push(Immediate(1)),
+                    Pop(Register(Rdi)),
+                    Pop(Register(Rax)),
+                    Sub(Register(Rax), Register(Rdi)),
+                    Push(Register(Rax)),
+                    Pop(Register(Rax)),
+                    Ret,
+                ],
+                Statement::Expression(Expr::Infix(
+                    Infix::Minus,
+                    Box::new(Expr::Literal(Literal::Int(1))),
+                    Box::new(Expr::Literal(Literal::Int(1))),
+                )),
+            ),
+            (
+                vec![
+                    Push(Immediate(1)),
+                    Push(Immediate(1)),
+                    Pop(Register(Rdi)),
+                    Pop(Register(Rax)),
+                    Imul(Register(Rax), Register(Rdi)),
+                    Push(Register(Rax)),
+                    Pop(Register(Rax)),
+                    Ret,
+                ],
+                Statement::Expression(Expr::Infix(
+                    Infix::Multiply,
+                    Box::new(Expr::Literal(Literal::Int(1))),
+                    Box::new(Expr::Literal(Literal::Int(1))),
+                )),
+            ),
+            (
+                vec![
+                    Push(Immediate(1)),
+                    Push(Immediate(1)),
+                    Pop(Register(Rdi)),
+                    Pop(Register(Rax)),
+                    Cqo,
+                    Idiv(Register(Rdi)),
+                    Push(Register(Rax)),
+                    Pop(Register(Rax)),
+                    Ret,
+                ],
+                Statement::Expression(Expr::Infix(
+                    Infix::Divide,
+                    Box::new(Expr::Literal(Literal::Int(1))),
+                    Box::new(Expr::Literal(Literal::Int(1))),
+                )),
+            ),
+            (
+                vec![
+                    Mov(Register(Rax), Immediate(10_000_000_000)),
+                    Push(Register(Rax)),
+                    Pop(Register(Rax)),
+                    Ret,
+                ],
+                Statement::Expression(Expr::Literal(Literal::Int(10_000_000_000))),
+            ),
+            (
+                vec![Push(Immediate(1)), Pop(Register(Rax)), Ret],
+                Statement::Expression(Expr::Prefix(
+                    Prefix::Plus,
+                    Box::new(Expr::Literal(Literal::Int(1))),
+                )),
+            ),
+            (
+                vec![
+                    Push(Immediate(1)),
+                    Pop(Register(Rax)),
+                    Neg(Register(Rax)),
+                    Push(Register(Rax)),
+                    Pop(Register(Rax)),
+                    Ret,
+                ],
+                Statement::Expression(Expr::Prefix(
+                    Prefix::Minus,
+                    Box::new(Expr::Literal(Literal::Int(1))),
+                )),
+            ),
+            (
+                vec![
+                    Push(Immediate(1)),
+                    Pop(Register(Rax)),
+                    Not(Register(Rax)),
+                    Push(Register(Rax)),
+                    Pop(Register(Rax)),
+                    Ret,
+                ],
+                Statement::Expression(Expr::Prefix(
+                    Prefix::BitNot,
+                    Box::new(Expr::Literal(Literal::Int(1))),
+                )),
+            ),
+            (
+                vec![
+                    Push(Immediate(1)),
+                    Pop(Register(Rax)),
+                    Cmp(Register(Rax), Immediate(0)),
+                    Sete(Byte(Rax)),
+                    Movzx(Register(Rax), Byte(Rax)),
+                    Push(Register(Rax)),
+                    Pop(Register(Rax)),
+                    Ret,
+                ],
+                Statement::Expression(Expr::Prefix(
+                    Prefix::Not,
+                    Box::new(Expr::Literal(Literal::Int(1))),
+                )),
+            ),
+        ];
+
+        let c = Compiler::new();
+
+        for (want, input) in tests {
+            assert_eq!(want, generate(&c, input));
+        }
+    }
+
+    #[test]
+    fn test_generate_registers() {
+        let tests = vec![
+            (
+                vec![
+                    Mov(Register(Rcx), Immediate(1)),
+                    Mov(Register(Rax), Register(Rcx)),
+                    Ret,
+                ],
+                "1",
+            ),
+            (
+                vec![
+                    Mov(Register(Rcx), Immediate(2)),
+                    Mov(Register(Rsi), Immediate(3)),
+                    Imul(Register(Rcx), Register(Rsi)),
+                    Mov(Register(Rsi), Immediate(1)),
+                    Add(Register(Rsi), Register(Rcx)),
+                    Mov(Register(Rax), Register(Rsi)),
+                    Ret,
+                ],
+                "1+2*3",
+            ),
+            (
+                vec![
+                    Mov(Register(Rcx), Immediate(3)),
+                    Mov(Register(Rsi), Immediate(2)),
+                    Sub(Register(Rcx), Register(Rsi)),
+                    Neg(Register(Rcx)),
+                    Mov(Register(Rsi), Immediate(8)),
+                    Mov(Register(Rax), Register(Rsi)),
+                    Cqo,
+                    Idiv(Register(Rcx)),
+                    Mov(Register(Rsi), Register(Rax)),
+                    Mov(Register(Rax), Register(Rsi)),
+                    Ret,
+                ],
+                "8/-(3-2)",
+            ),
+            (
+                vec![
+                    Mov(Register(Rcx), Immediate(0)),
+                    Cmp(Register(Rcx), Immediate(0)),
+                    Sete(Byte(Rcx)),
+                    Movzx(Register(Rcx), Byte(Rcx)),
+                    Not(Register(Rcx)),
+                    Mov(Register(Rax), Register(Rcx)),
+                    Ret,
+                ],
+                "~!0",
+            ),
+        ];
+
+        let c = Compiler::new().allocation(Allocation::Registers);
+
+        for (want, input) in tests {
+            assert_eq!(want, generate(&c, parse(input)));
+        }
+    }
+
+    #[test]
+    fn test_generate_registers_spill() {
+        // A complete tree of depth 8 needs one register more than there are.
+        fn tree(depth: usize) -> Expr {
+            match depth {
+                0 => Expr::Literal(Literal::Int(1)),
+                _ => Expr::Infix(
+                    Infix::Plus,
+                    Box::new(tree(depth - 1)),
+                    Box::new(tree(depth - 1)),
+                ),
+            }
+        }
+
+        let c = Compiler::new().allocation(Allocation::Registers);
+        let slot = Memory(Address::new(Rbp, -8));
+
+        let fits = generate(&c, Statement::Expression(tree(6)));
+        assert!(fits.iter().all(|i| !i.reads().contains(&Rbp)));
+
+        let spills = generate(&c, Statement::Expression(tree(7)));
+        assert_eq!(
+            vec![
+                Push(Register(Rbp)),
+                Mov(Register(Rbp), Register(Rsp)),
+                Sub(Register(Rsp), Immediate(8)),
+            ],
+            spills[..3].to_vec()
+        );
+        assert!(spills.contains(&Mov(slot, Register(Rcx))));
+        assert!(spills.contains(&Mov(Register(Rdi), slot)));
+        assert_eq!(
+            vec![Mov(Register(Rsp), Register(Rbp)), Pop(Register(Rbp)), Ret],
+            spills[spills.len() - 3..].to_vec()
+        );
+    }
+
+    #[test]
+    fn test_instruction_count() {
+        let tests = vec![
+            ("1", 3, 3),
+            ("10+1", 8, 5),
+            ("2*3+4*5", 18, 9),
+            ("-(30)*2-(-(60))", 19, 9),
+            ("(1+2)*(3+4)/(5-6)", 29, 16),
+        ];
+
+        for (input, stack, registers) in tests {
+            let s = generate(&Compiler::new(), parse(input));
+            let r = generate(
+                &Compiler::new().allocation(Allocation::Registers),
+                parse(input),
+            );
+
+            assert_eq!(stack, s.len(), "{}", input);
+            assert_eq!(registers, r.len(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_peephole() {
+        let tests = vec![
+            (
+                "1+2",
+                vec![
+                    Mov(Register(Rdi), Immediate(2)),
+                    Mov(Register(Rax), Immediate(1)),
+                    Add(Register(Rax), Register(Rdi)),
+                    Ret,
+                ],
+            ),
+            (
+                "(1+2)*8",
+                vec![
+                    Mov(Register(Rdi), Immediate(2)),
+                    Mov(Register(Rax), Immediate(1)),
+                    Add(Register(Rax), Register(Rdi)),
+                    Shl(Register(Rax), Immediate(3)),
+                    Ret,
+                ],
+            ),
+            (
+                "-(3)*5",
+                vec![
+                    Mov(Register(Rax), Immediate(3)),
+                    Neg(Register(Rax)),
+                    Lea(
+                        Rax,
+                        Address {
+                            base: Rax,
+                            index: Some((Rax, 4)),
+                            displacement: 0,
+                        },
+                    ),
+                    Ret,
+                ],
+            ),
+        ];
+
+        let c = Compiler::new().peephole(true);
+
+        for (input, want) in tests {
+            assert_eq!(want, generate(&c, parse(input)));
+        }
+    }
+
+    #[test]
+    fn test_generate_print_result() {
+        let c = Compiler::new().peephole(true).print_result(true);
+        let assembly = c.generate(&lower(&parse("1+2")));
+
+        assert_eq!("main", assembly.symbol);
+        assert_eq!(Call(EXPRESSION), assembly.instructions[0]);
+        // The expression follows the printing code as a function.
+        let body: Vec<Instruction> = assembly
+            .instructions
+            .into_iter()
+            .skip_while(|i| *i != Label(EXPRESSION))
+            .skip(1)
+            .collect();
+        assert_eq!(
+            generate(&Compiler::new().peephole(true), parse("1+2")),
+            body
+        );
+    }
+
+    #[test]
+    fn test_generate_checked() {
+        let c = Compiler::new()
+            .allocation(Allocation::Registers)
+            .check_division(true)
+            .check_overflow(true);
+        let division = Compiler::new()
+            .allocation(Allocation::Registers)
+            .check_division(true);
+        let tests = vec![
+            (
+                &c,
+                "-(1+2)",
+                vec![
+                    Mov(Register(Rcx), Immediate(1)),
+                    Mov(Register(Rsi), Immediate(2)),
+                    Add(Register(Rcx), Register(Rsi)),
+                    J(Condition::O, OVERFLOW),
+                    Neg(Register(Rcx)),
+                    J(Condition::O, OVERFLOW),
+                    Mov(Register(Rax), Register(Rcx)),
+                    Ret,
+                ],
+            ),
+            (
+                &c,
+                "6/3",
+                vec![
+                    Mov(Register(Rcx), Immediate(6)),
+                    Mov(Register(Rsi), Immediate(3)),
+                    Cmp(Register(Rsi), Immediate(0)),
+                    J(Condition::E, DIVIDE_BY_ZERO),
+                    Mov(Register(Rax), Register(Rcx)),
+                    Cmp(Register(Rsi), Immediate(-1)),
+                    Sete(Byte(Rdx)),
+                    Movzx(Register(Rdx), Byte(Rdx)),
+                    Cmp(Register(Rax), Register(Rdx)),
+                    J(Condition::O, OVERFLOW),
+                    Cqo,
+                    Idiv(Register(Rsi)),
+                    Mov(Register(Rcx), Register(Rax)),
+                    Mov(Register(Rax), Register(Rcx)),
+                    Ret,
+                ],
+            ),
+            (
+                // Dividing i64::MIN by -1 is caught even when the rest of
+                // the arithmetic wraps.
+                &division,
+                "-(1+6)/3",
+                vec![
+                    Mov(Register(Rcx), Immediate(1)),
+                    Mov(Register(Rsi), Immediate(6)),
+                    Add(Register(Rcx), Register(Rsi)),
+                    Neg(Register(Rcx)),
+                    Mov(Register(Rsi), Immediate(3)),
+                    Cmp(Register(Rsi), Immediate(0)),
+                    J(Condition::E, DIVIDE_BY_ZERO),
+                    Mov(Register(Rax), Register(Rcx)),
+                    Cmp(Register(Rsi), Immediate(-1)),
+                    Sete(Byte(Rdx)),
+                    Movzx(Register(Rdx), Byte(Rdx)),
+                    Cmp(Register(Rax), Register(Rdx)),
+                    J(Condition::O, OVERFLOW),
+                    Cqo,
+                    Idiv(Register(Rsi)),
+                    Mov(Register(Rcx), Register(Rax)),
+                    Mov(Register(Rax), Register(Rcx)),
+                    Ret,
+                ],
+            ),
+        ];
+
+        for (c, input, want) in tests {
+            assert_eq!(want, generate(c, parse(input)));
+        }
+    }
+
+    #[test]
+    fn test_generate_traps() {
+        let c = Compiler::new().define_traps(true);
+        let code = generate(&c, parse("1"));
+
+        // Each trap writes its message to stderr and exits with status 1.
+        let mut labels = vec![];
+        for (n, i) in code.iter().enumerate() {
+            if let Label(label) = i {
+                labels.push(*label);
+                assert!(code[n..].contains(&Mov(Register(Rdi), Immediate(2))));
+                assert!(code[n..].contains(&Mov(Register(Rdi), Immediate(1))));
+            }
+        }
+        assert_eq!(vec![DIVIDE_BY_ZERO, OVERFLOW], labels);
+        // "Divide a number by 0.\n" is 22 bytes, pushed in 3 chunks from the
+        // end.
+        let n = code
+            .iter()
+            .position(|i| *i == Label(DIVIDE_BY_ZERO))
+            .unwrap();
+        assert_eq!(Mov(Register(Rax), Immediate(0x0a2e_3020_7962)), code[n + 1]);
+        assert_eq!(Mov(Register(Rdx), Immediate(22)), code[n + 10]);
+    }
+}
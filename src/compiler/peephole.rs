@@ -0,0 +1,247 @@
+use crate::compiler::x86::{Address, Instruction, Operand, Register};
+
+/// Rewrites short windows of instructions into cheaper equivalents until no
+/// rule applies any more.
+pub fn optimize(mut code: Vec<Instruction>) -> Vec<Instruction> {
+    while let Some(rewritten) = rewrite_once(&code) {
+        code = rewritten;
+    }
+    code
+}
+
+fn rewrite_once(code: &[Instruction]) -> Option<Vec<Instruction>> {
+    for n in 0..code.len() {
+        if let Some((len, replacement)) = rewrite_at(code, n) {
+            let mut rewritten = code[..n].to_vec();
+            rewritten.extend(replacement);
+            rewritten.extend_from_slice(&code[n + len..]);
+            return Some(rewritten);
+        }
+    }
+    None
+}
+
+/// Tries every rule on the window starting at `n`, returning how many
+/// instructions to replace and what with.
+fn rewrite_at(code: &[Instruction], n: usize) -> Option<(usize, Vec<Instruction>)> {
+    use Instruction::*;
+    use Operand::{Immediate as Imm, Register as Reg};
+
+    match code[n..] {
+        // mov rax, rax
+        [Mov(a, b), ..] if a == b => Some((1, vec![])),
+
+        // push rax; pop rdi => mov rdi, rax
+        [Push(a), Pop(b), ..] if can_move(b, a) => Some((2, mov(b, a))),
+
+        // push rax; mov rdi, 1; pop rcx => mov rdi, 1; mov rcx, rax
+        [Push(a), m @ Mov(Reg(r), Imm(_)), Pop(b), ..]
+            if !a.mentions(r) && !b.mentions(r) && can_move(b, a) =>
+        {
+            Some((3, [vec![m], mov(b, a)].concat()))
+        }
+
+        // mov rdi, 8; imul rax, rdi => shl rax, 3
+        [Mov(Reg(r), Imm(k)), Imul(Reg(x), Reg(s)), ..]
+            if s == r && x != r && is_dead_after(code, n + 2, r) =>
+        {
+            multiply(x, k).map(|c| (2, c))
+        }
+
+        // mov rdi, 8; mov rax, rcx; imul rax, rdi => mov rax, rcx; shl rax, 3
+        [Mov(Reg(r), Imm(k)), m @ Mov(Reg(x), y), Imul(Reg(x2), Reg(s)), ..]
+            if s == r && x == x2 && x != r && !y.mentions(r) && is_dead_after(code, n + 3, r) =>
+        {
+            multiply(x, k).map(|c| (3, [vec![m], c].concat()))
+        }
+
+        _ => None,
+    }
+}
+
+fn mov(dst: Operand, src: Operand) -> Vec<Instruction> {
+    match dst == src {
+        true => vec![],
+        false => vec![Instruction::Mov(dst, src)],
+    }
+}
+
+/// Whether `mov dst, src` is encodable.
+fn can_move(dst: Operand, src: Operand) -> bool {
+    !matches!(
+        (dst, src),
+        (Operand::Memory(_), Operand::Memory(_)) | (Operand::Immediate(_), _)
+    )
+}
+
+/// Strength reduction of `x = x * k`.
+fn multiply(x: Register, k: i64) -> Option<Vec<Instruction>> {
+    match k {
+        1 => Some(vec![]),
+        k if k > 0 && k.count_ones() == 1 => Some(vec![Instruction::Shl(
+            Operand::Register(x),
+            Operand::Immediate(k.trailing_zeros() as i64),
+        )]),
+        3 | 5 | 9 => Some(vec![Instruction::Lea(
+            x,
+            Address {
+                base: x,
+                index: Some((x, (k - 1) as u8)),
+                displacement: 0,
+            },
+        )]),
+        _ => None,
+    }
+}
+
+/// Whether the value in `r` is never read again from `code[from]` on.
+fn is_dead_after(code: &[Instruction], from: usize, r: Register) -> bool {
+    for i in &code[from..] {
+        // The value may be read wherever control goes next, and a `jo`
+        // tests the overflow flag `shl` and `lea` would not set.
+        if matches!(
+            i,
+            Instruction::Label(_) | Instruction::J(_, _) | Instruction::Call(_)
+        ) {
+            return false;
+        }
+        if i.reads().contains(&r) {
+            return false;
+        }
+        if i.writes().contains(&r) || *i == Instruction::Ret {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::peephole::optimize;
+    use crate::compiler::x86::Instruction::*;
+    use crate::compiler::x86::Operand::*;
+    use crate::compiler::x86::Register::*;
+    use crate::compiler::x86::{Address, Instruction};
+
+    #[test]
+    fn test_push_pop() {
+        let tests: Vec<(Vec<Instruction>, Vec<Instruction>)> = vec![
+            (
+                vec![Push(Register(Rax)), Pop(Register(Rax)), Ret],
+                vec![Ret],
+            ),
+            (
+                vec![Push(Immediate(1)), Pop(Register(Rdi)), Ret],
+                vec![Mov(Register(Rdi), Immediate(1)), Ret],
+            ),
+            (
+                vec![
+                    Push(Immediate(1)),
+                    Push(Immediate(2)),
+                    Pop(Register(Rdi)),
+                    Pop(Register(Rax)),
+                    Add(Register(Rax), Register(Rdi)),
+                    Push(Register(Rax)),
+                    Pop(Register(Rax)),
+                    Ret,
+                ],
+                vec![
+                    Mov(Register(Rdi), Immediate(2)),
+                    Mov(Register(Rax), Immediate(1)),
+                    Add(Register(Rax), Register(Rdi)),
+                    Ret,
+                ],
+            ),
+            (
+                vec![
+                    Push(Register(Rdi)),
+                    Mov(Register(Rdi), Immediate(1)),
+                    Pop(Register(Rax)),
+                    Ret,
+                ],
+                vec![
+                    Push(Register(Rdi)),
+                    Mov(Register(Rdi), Immediate(1)),
+                    Pop(Register(Rax)),
+                    Ret,
+                ],
+            ),
+        ];
+
+        for (input, want) in tests {
+            assert_eq!(want, optimize(input));
+        }
+    }
+
+    #[test]
+    fn test_multiply_by_constant() {
+        let lea = |k: u8| Address {
+            base: Rax,
+            index: Some((Rax, k)),
+            displacement: 0,
+        };
+
+        let tests: Vec<(i64, Vec<Instruction>)> = vec![
+            (1, vec![Ret]),
+            (2, vec![Shl(Register(Rax), Immediate(1)), Ret]),
+            (8, vec![Shl(Register(Rax), Immediate(3)), Ret]),
+            (3, vec![Lea(Rax, lea(2)), Ret]),
+            (5, vec![Lea(Rax, lea(4)), Ret]),
+            (9, vec![Lea(Rax, lea(8)), Ret]),
+            (
+                7,
+                vec![
+                    Mov(Register(Rdi), Immediate(7)),
+                    Imul(Register(Rax), Register(Rdi)),
+                    Ret,
+                ],
+            ),
+            (
+                -4,
+                vec![
+                    Mov(Register(Rdi), Immediate(-4)),
+                    Imul(Register(Rax), Register(Rdi)),
+                    Ret,
+                ],
+            ),
+        ];
+
+        for (k, want) in tests {
+            let input = vec![
+                Mov(Register(Rdi), Immediate(k)),
+                Imul(Register(Rax), Register(Rdi)),
+                Ret,
+            ];
+            assert_eq!(want, optimize(input));
+        }
+    }
+
+    #[test]
+    fn test_multiply_keeps_live_register() {
+        let input = vec![
+            Mov(Register(Rdi), Immediate(8)),
+            Imul(Register(Rax), Register(Rdi)),
+            Add(Register(Rax), Register(Rdi)),
+            Ret,
+        ];
+
+        assert_eq!(input.clone(), optimize(input));
+    }
+
+    #[test]
+    fn test_multiply_across_move() {
+        let input = vec![
+            Mov(Register(Rdi), Immediate(4)),
+            Mov(Register(Rax), Register(Rcx)),
+            Imul(Register(Rax), Register(Rdi)),
+            Ret,
+        ];
+        let want = vec![
+            Mov(Register(Rax), Register(Rcx)),
+            Shl(Register(Rax), Immediate(2)),
+            Ret,
+        ];
+
+        assert_eq!(want, optimize(input));
+    }
+}
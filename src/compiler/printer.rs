@@ -0,0 +1,267 @@
+use crate::compiler::x86::{Address, Assembly, Instruction, Operand};
+
+/// Assembler dialect to print instructions in.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Syntax {
+    Intel,
+    /// The GNU assembler default: `%` registers, `$` immediates, size
+    /// suffixes and the source operand first.
+    Att,
+}
+
+pub fn print(assembly: &Assembly, syntax: Syntax) -> String {
+    let mut code = String::new();
+
+    match syntax {
+        Syntax::Intel => code.push_str(".intel_syntax noprefix\n"),
+        Syntax::Att => {}
+    }
+    code.push_str(&format!(".global {}\n", assembly.symbol));
+    code.push_str(&format!("{}:\n", assembly.symbol));
+
+    for i in &assembly.instructions {
+        match *i {
+            Instruction::Label(label) => code.push_str(&format!("{}:\n", label)),
+            _ => {
+                code.push_str("  ");
+                code.push_str(&print_instruction(i, syntax));
+                code.push('\n');
+            }
+        }
+    }
+
+    code
+}
+
+pub fn print_instruction(i: &Instruction, syntax: Syntax) -> String {
+    match syntax {
+        Syntax::Intel => intel_instruction(i),
+        Syntax::Att => att_instruction(i),
+    }
+}
+
+fn intel_instruction(i: &Instruction) -> String {
+    let operands: Vec<String> = match *i {
+        Instruction::J(_, label) | Instruction::Call(label) => vec![label.to_string()],
+        // lea computes the address without accessing memory.
+        Instruction::Lea(r, address) => vec![r.name().to_string(), intel_address(&address)],
+        Instruction::MovByte(address, r) => vec![
+            format!("byte ptr {}", intel_address(&address)),
+            r.low_name().to_string(),
+        ],
+        _ => i.operands().iter().map(intel_operand).collect(),
+    };
+
+    match operands.is_empty() {
+        true => i.mnemonic().to_string(),
+        false => format!("{} {}", i.mnemonic(), operands.join(", ")),
+    }
+}
+
+fn intel_operand(operand: &Operand) -> String {
+    match *operand {
+        Operand::Register(r) => r.name().to_string(),
+        Operand::Byte(r) => r.low_name().to_string(),
+        Operand::Immediate(i) => i.to_string(),
+        Operand::Memory(address) => format!("qword ptr {}", intel_address(&address)),
+    }
+}
+
+fn intel_address(address: &Address) -> String {
+    let mut s = format!("[{}", address.base.name());
+    if let Some((index, scale)) = address.index {
+        s.push_str(&format!("+{}*{}", index.name(), scale));
+    }
+    match address.displacement {
+        0 => s.push(']'),
+        d if d < 0 => s.push_str(&format!("{}]", d)),
+        d => s.push_str(&format!("+{}]", d)),
+    }
+    s
+}
+
+fn att_instruction(i: &Instruction) -> String {
+    let mnemonic = match *i {
+        Instruction::Movzx(_, _) => "movzbq".to_string(),
+        Instruction::Cqo => "cqto".to_string(),
+        Instruction::MovByte(_, _) => "movb".to_string(),
+        Instruction::Sete(_) | Instruction::Ret | Instruction::J(_, _) | Instruction::Syscall => {
+            i.mnemonic().to_string()
+        }
+        _ => format!("{}q", i.mnemonic()),
+    };
+    let operands: Vec<String> = match *i {
+        Instruction::J(_, label) | Instruction::Call(label) => vec![label.to_string()],
+        _ => i.operands().iter().rev().map(att_operand).collect(),
+    };
+
+    match operands.is_empty() {
+        true => mnemonic,
+        false => format!("{} {}", mnemonic, operands.join(", ")),
+    }
+}
+
+fn att_operand(operand: &Operand) -> String {
+    match *operand {
+        Operand::Register(r) => format!("%{}", r.name()),
+        Operand::Byte(r) => format!("%{}", r.low_name()),
+        Operand::Immediate(i) => format!("${}", i),
+        Operand::Memory(address) => {
+            let mut s = match address.displacement {
+                0 => String::new(),
+                d => d.to_string(),
+            };
+            s.push_str(&format!("(%{}", address.base.name()));
+            if let Some((index, scale)) = address.index {
+                s.push_str(&format!(",%{},{}", index.name(), scale));
+            }
+            s.push(')');
+            s
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::printer::*;
+    use crate::compiler::x86::Instruction::*;
+    use crate::compiler::x86::Operand::*;
+    use crate::compiler::x86::Register::*;
+    use crate::compiler::x86::{Address, Assembly, Condition};
+
+    fn scaled(base: crate::compiler::x86::Register, scale: u8) -> Address {
+        Address {
+            base,
+            index: Some((base, scale)),
+            displacement: 0,
+        }
+    }
+
+    #[test]
+    fn test_print_intel() {
+        let tests = vec![
+            ("push 1", Push(Immediate(1))),
+            ("pop rdi", Pop(Register(Rdi))),
+            (
+                "mov qword ptr [rbp-8], rcx",
+                Mov(Memory(Address::new(Rbp, -8)), Register(Rcx)),
+            ),
+            ("movzx rsi, sil", Movzx(Register(Rsi), Byte(Rsi))),
+            ("sete r8b", Sete(Byte(R8))),
+            ("lea rax, [rax+rax*4]", Lea(Rax, scaled(Rax, 4))),
+            ("shl rax, 3", Shl(Register(Rax), Immediate(3))),
+            (
+                "idiv qword ptr [rbp+16]",
+                Idiv(Memory(Address::new(Rbp, 16))),
+            ),
+            ("cqo", Cqo),
+            ("ret", Ret),
+            ("je divide_by_zero", J(Condition::E, "divide_by_zero")),
+            ("jge print_write", J(Condition::Ge, "print_write")),
+            (
+                "mov byte ptr [rsi], dil",
+                MovByte(Address::new(Rsi, 0), Rdi),
+            ),
+            ("call body", Call("body")),
+            ("syscall", Syscall),
+        ];
+
+        for (want, i) in tests {
+            assert_eq!(want, print_instruction(&i, Syntax::Intel));
+        }
+    }
+
+    #[test]
+    fn test_print_att() {
+        let tests = vec![
+            ("pushq $1", Push(Immediate(1))),
+            ("popq %rdi", Pop(Register(Rdi))),
+            (
+                "movq %rcx, -8(%rbp)",
+                Mov(Memory(Address::new(Rbp, -8)), Register(Rcx)),
+            ),
+            ("movzbq %sil, %rsi", Movzx(Register(Rsi), Byte(Rsi))),
+            ("sete %r8b", Sete(Byte(R8))),
+            ("leaq (%rax,%rax,4), %rax", Lea(Rax, scaled(Rax, 4))),
+            ("shlq $3, %rax", Shl(Register(Rax), Immediate(3))),
+            ("subq %rdi, %rax", Sub(Register(Rax), Register(Rdi))),
+            ("idivq 16(%rbp)", Idiv(Memory(Address::new(Rbp, 16)))),
+            ("cqto", Cqo),
+            ("ret", Ret),
+            ("je divide_by_zero", J(Condition::E, "divide_by_zero")),
+            ("jge print_write", J(Condition::Ge, "print_write")),
+            ("movb %dil, (%rsi)", MovByte(Address::new(Rsi, 0), Rdi)),
+            ("callq body", Call("body")),
+            ("syscall", Syscall),
+        ];
+
+        for (want, i) in tests {
+            assert_eq!(want, print_instruction(&i, Syntax::Att));
+        }
+    }
+
+    #[test]
+    fn test_print() {
+        let assembly = Assembly {
+            symbol: "main".to_string(),
+            instructions: vec![Mov(Register(Rax), Immediate(42)), Ret],
+        };
+        let want = r#".intel_syntax noprefix
+.global main
+main:
+  mov rax, 42
+  ret
+"#;
+
+        assert_eq!(want, print(&assembly, Syntax::Intel));
+    }
+
+    #[test]
+    fn test_print_both_syntaxes() {
+        let assembly = Assembly {
+            symbol: "main".to_string(),
+            instructions: vec![
+                Push(Immediate(8)),
+                Push(Immediate(3)),
+                Pop(Register(Rdi)),
+                Pop(Register(Rax)),
+                Cqo,
+                Idiv(Register(Rdi)),
+                Cmp(Register(Rax), Immediate(0)),
+                Sete(Byte(Rax)),
+                Movzx(Register(Rax), Byte(Rax)),
+                Ret,
+            ],
+        };
+        let intel = r#".intel_syntax noprefix
+.global main
+main:
+  push 8
+  push 3
+  pop rdi
+  pop rax
+  cqo
+  idiv rdi
+  cmp rax, 0
+  sete al
+  movzx rax, al
+  ret
+"#;
+        let att = r#".global main
+main:
+  pushq $8
+  pushq $3
+  popq %rdi
+  popq %rax
+  cqto
+  idivq %rdi
+  cmpq $0, %rax
+  sete %al
+  movzbq %al, %rax
+  ret
+"#;
+
+        assert_eq!(intel, print(&assembly, Syntax::Intel));
+        assert_eq!(att, print(&assembly, Syntax::Att));
+    }
+}
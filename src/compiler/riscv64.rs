@@ -0,0 +1,324 @@
+use crate::compiler::EXPRESSION;
+use crate::ir::{BinaryOp, Function, Instruction as Ir, UnaryOp};
+use std::fmt;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Register {
+    Sp,
+    /// Frame pointer.
+    S0,
+    A(u8),
+    T(u8),
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Register::Sp => write!(f, "sp"),
+            Register::S0 => write!(f, "s0"),
+            Register::A(n) => write!(f, "a{}", n),
+            Register::T(n) => write!(f, "t{}", n),
+        }
+    }
+}
+
+/// The subset of RV64IM the compiler emits, destination first. `li`, `mv`,
+/// `neg`, `not` and `seqz` are assembler pseudo-instructions.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Instruction {
+    Li(Register, i64),
+    Mv(Register, Register),
+    Neg(Register, Register),
+    Not(Register, Register),
+    /// Sets to 1 if the source is zero, 0 otherwise.
+    Seqz(Register, Register),
+    Add(Register, Register, Register),
+    Sub(Register, Register, Register),
+    Mul(Register, Register, Register),
+    Div(Register, Register, Register),
+    Addi(Register, Register, i64),
+    Ld(Register, Register, i64),
+    Sd(Register, Register, i64),
+    Ret,
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::Li(d, i) => write!(f, "li {}, {}", d, i),
+            Instruction::Mv(d, s) => write!(f, "mv {}, {}", d, s),
+            Instruction::Neg(d, s) => write!(f, "neg {}, {}", d, s),
+            Instruction::Not(d, s) => write!(f, "not {}, {}", d, s),
+            Instruction::Seqz(d, s) => write!(f, "seqz {}, {}", d, s),
+            Instruction::Add(d, a, b) => write!(f, "add {}, {}, {}", d, a, b),
+            Instruction::Sub(d, a, b) => write!(f, "sub {}, {}, {}", d, a, b),
+            Instruction::Mul(d, a, b) => write!(f, "mul {}, {}, {}", d, a, b),
+            Instruction::Div(d, a, b) => write!(f, "div {}, {}, {}", d, a, b),
+            Instruction::Addi(d, s, i) => write!(f, "addi {}, {}, {}", d, s, i),
+            Instruction::Ld(d, base, i) => write!(f, "ld {}, {}({})", d, i, base),
+            Instruction::Sd(s, base, i) => write!(f, "sd {}, {}({})", s, i, base),
+            Instruction::Ret => write!(f, "ret"),
+        }
+    }
+}
+
+const MAIN: &str = r#".global main
+main:
+  addi sp, sp, -16
+  sd ra, 8(sp)
+  call {expression}
+  mv a1, a0
+  lla a0, .Lformat
+  call printf
+  li a0, 0
+  ld ra, 8(sp)
+  addi sp, sp, 16
+  ret
+.section .rodata
+.Lformat:
+  .asciz "%ld\n"
+.text
+"#;
+
+pub fn print(symbol: &str, code: &[Instruction]) -> String {
+    let mut s = String::new();
+
+    s.push_str(&format!(".global {}\n", symbol));
+    s.push_str(&format!("{}:\n", symbol));
+    for i in code {
+        s.push_str(&format!("  {}\n", i));
+    }
+
+    s
+}
+
+/// Prints `code` as the function `main` calls, and a `main` printing its
+/// result with the C library's `printf`.
+pub fn print_program(code: &[Instruction]) -> String {
+    let mut s = MAIN.replace("{expression}", EXPRESSION);
+    s.push_str(&print(EXPRESSION, code));
+    s
+}
+
+// t0 to t6 hold the operand stack; a1 and a2 are free as scratch registers
+// because main takes no arguments.
+const STACK_REGISTERS: usize = 7;
+const SCRATCH: [Register; 2] = [Register::A(1), Register::A(2)];
+
+/// Generates code that leaves the result of `f` in a0.
+///
+/// Operands are kept on a stack mapped onto t0 to t6 and spilled to slots
+/// below the saved frame pointer beyond that depth.
+///
+/// `div` yields -1 for a division by zero instead of trapping.
+pub fn generate(f: &Function) -> Vec<Instruction> {
+    let mut g = Generator {
+        depth: 0,
+        max_depth: 0,
+        body: vec![],
+    };
+
+    for i in &f.instructions {
+        g.generate_instruction(i);
+    }
+
+    // The saved frame pointer takes the first 8 bytes, and sp has to stay
+    // 16-byte aligned.
+    let slots = g.max_depth.saturating_sub(STACK_REGISTERS) as i64;
+    let frame = (8 + slots * 8 + 15) / 16 * 16;
+
+    let mut code = vec![
+        Instruction::Addi(Register::Sp, Register::Sp, -frame),
+        Instruction::Sd(Register::S0, Register::Sp, frame - 8),
+        Instruction::Addi(Register::S0, Register::Sp, frame),
+    ];
+    code.append(&mut g.body);
+    code.push(Instruction::Mv(Register::A(0), location(0)));
+    code.push(Instruction::Ld(Register::S0, Register::Sp, frame - 8));
+    code.push(Instruction::Addi(Register::Sp, Register::Sp, frame));
+    code.push(Instruction::Ret);
+
+    code
+}
+
+struct Generator {
+    depth: usize,
+    max_depth: usize,
+    body: Vec<Instruction>,
+}
+
+fn location(depth: usize) -> Register {
+    Register::T(depth as u8)
+}
+
+/// Offset from s0 of the slot for stack entry `depth`.
+fn slot(depth: usize) -> i64 {
+    -16 - (depth - STACK_REGISTERS) as i64 * 8
+}
+
+impl Generator {
+    fn generate_instruction(&mut self, i: &Ir) {
+        match *i {
+            Ir::Const(_, i) => {
+                let r = self.target(self.depth);
+                self.body.push(Instruction::Li(r, i));
+                self.push(r);
+            }
+            Ir::Unary(_, op, _) => {
+                self.depth -= 1;
+                let s = self.read(self.depth, SCRATCH[0]);
+                let r = self.target(self.depth);
+                match op {
+                    UnaryOp::Neg => self.body.push(Instruction::Neg(r, s)),
+                    UnaryOp::Not => self.body.push(Instruction::Not(r, s)),
+                    UnaryOp::LogicalNot => self.body.push(Instruction::Seqz(r, s)),
+                }
+                self.push(r);
+            }
+            Ir::Binary(_, op, a, b) => {
+                self.depth -= 2;
+                // The operand computed last is on top of the stack.
+                let (x, y) = match a < b {
+                    true => (self.depth, self.depth + 1),
+                    false => (self.depth + 1, self.depth),
+                };
+                let ra = self.read(x, SCRATCH[0]);
+                let rb = self.read(y, SCRATCH[1]);
+                let r = self.target(self.depth);
+                match op {
+                    BinaryOp::Add => self.body.push(Instruction::Add(r, ra, rb)),
+                    BinaryOp::Sub => self.body.push(Instruction::Sub(r, ra, rb)),
+                    BinaryOp::Mul => self.body.push(Instruction::Mul(r, ra, rb)),
+                    BinaryOp::Div => self.body.push(Instruction::Div(r, ra, rb)),
+                }
+                self.push(r);
+            }
+        }
+    }
+
+    /// The register holding stack entry `depth`, loading it into `scratch`
+    /// if it was spilled.
+    fn read(&mut self, depth: usize, scratch: Register) -> Register {
+        match depth < STACK_REGISTERS {
+            true => location(depth),
+            false => {
+                self.body
+                    .push(Instruction::Ld(scratch, Register::S0, slot(depth)));
+                scratch
+            }
+        }
+    }
+
+    /// The register to compute stack entry `depth` into.
+    fn target(&self, depth: usize) -> Register {
+        match depth < STACK_REGISTERS {
+            true => location(depth),
+            false => SCRATCH[0],
+        }
+    }
+
+    /// Pushes the entry computed into `r`, storing it if it lives in memory.
+    fn push(&mut self, r: Register) {
+        if self.depth >= STACK_REGISTERS {
+            self.body
+                .push(Instruction::Sd(r, Register::S0, slot(self.depth)));
+        }
+        self.depth += 1;
+        self.max_depth = self.max_depth.max(self.depth);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::Statement;
+    use crate::compiler::riscv64::Instruction::*;
+    use crate::compiler::riscv64::Register::*;
+    use crate::compiler::riscv64::*;
+    use crate::ir::lower;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Statement {
+        Parser::new(Lexer::new(input)).parse().unwrap().remove(0)
+    }
+
+    #[test]
+    fn test_generate() {
+        let tests = vec![
+            ("7", vec![Li(T(0), 7)]),
+            (
+                "8/-(3-2)",
+                vec![
+                    Li(T(0), 3),
+                    Li(T(1), 2),
+                    Sub(T(0), T(0), T(1)),
+                    Neg(T(0), T(0)),
+                    Li(T(1), 8),
+                    Div(T(0), T(1), T(0)),
+                ],
+            ),
+            (
+                "!~0*5",
+                vec![
+                    Li(T(0), 0),
+                    Not(T(0), T(0)),
+                    Seqz(T(0), T(0)),
+                    Li(T(1), 5),
+                    Mul(T(0), T(0), T(1)),
+                ],
+            ),
+        ];
+
+        for (input, body) in tests {
+            let want = [
+                vec![Addi(Sp, Sp, -16), Sd(S0, Sp, 8), Addi(S0, Sp, 16)],
+                body,
+                vec![Mv(A(0), T(0)), Ld(S0, Sp, 8), Addi(Sp, Sp, 16), Ret],
+            ]
+            .concat();
+            assert_eq!(want, generate(&lower(&parse(input))));
+        }
+    }
+
+    #[test]
+    fn test_generate_spill() {
+        let mut input = "1".to_string();
+        for _ in 0..7 {
+            input = format!("({}+{})", input, input);
+        }
+        let code = generate(&lower(&parse(&input)));
+
+        assert_eq!(
+            vec![Addi(Sp, Sp, -16), Sd(S0, Sp, 8), Addi(S0, Sp, 16)],
+            code[..3].to_vec()
+        );
+        assert!(code.contains(&Sd(A(1), S0, -16)));
+        assert!(code.contains(&Ld(A(2), S0, -16)));
+    }
+
+    #[test]
+    fn test_print() {
+        let tests = vec![
+            ("li t0, -10000000000", Li(T(0), -10_000_000_000)),
+            ("seqz t1, t1", Seqz(T(1), T(1))),
+            ("div t0, t1, t0", Div(T(0), T(1), T(0))),
+            ("addi sp, sp, -16", Addi(Sp, Sp, -16)),
+            ("sd a1, -16(s0)", Sd(A(1), S0, -16)),
+            ("ld s0, 8(sp)", Ld(S0, Sp, 8)),
+        ];
+
+        for (want, i) in tests {
+            assert_eq!(want, i.to_string());
+        }
+    }
+
+    #[test]
+    fn test_print_program() {
+        let code = print_program(&generate(&lower(&parse("5"))));
+
+        assert!(code.starts_with(".global main\nmain:\n"));
+        assert!(code.contains("  call calc_expr\n"));
+        assert!(code.contains("  call printf\n"));
+        assert!(code.contains("\n.global calc_expr\ncalc_expr:\n"));
+    }
+}
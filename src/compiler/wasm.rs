@@ -0,0 +1,352 @@
+use crate::ir::{BinaryOp, Function, Instruction as Ir, UnaryOp, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// The subset of WebAssembly the compiler emits. Every value is an `i64`.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Instruction {
+    Const(i64),
+    Add,
+    Sub,
+    Mul,
+    DivS,
+    Xor,
+    /// Pushes an `i32` that is 1 if the operand is zero, 0 otherwise.
+    Eqz,
+    /// Zero-extends the `i32` on top of the stack.
+    ExtendI32U,
+    LocalGet(u32),
+    LocalSet(u32),
+}
+
+impl Instruction {
+    fn opcode(&self) -> u8 {
+        match *self {
+            Instruction::Const(_) => 0x42,
+            Instruction::Eqz => 0x50,
+            Instruction::Add => 0x7c,
+            Instruction::Sub => 0x7d,
+            Instruction::Mul => 0x7e,
+            Instruction::DivS => 0x7f,
+            Instruction::Xor => 0x85,
+            Instruction::ExtendI32U => 0xad,
+            Instruction::LocalGet(_) => 0x20,
+            Instruction::LocalSet(_) => 0x21,
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::Const(i) => write!(f, "i64.const {}", i),
+            Instruction::Add => write!(f, "i64.add"),
+            Instruction::Sub => write!(f, "i64.sub"),
+            Instruction::Mul => write!(f, "i64.mul"),
+            Instruction::DivS => write!(f, "i64.div_s"),
+            Instruction::Xor => write!(f, "i64.xor"),
+            Instruction::Eqz => write!(f, "i64.eqz"),
+            Instruction::ExtendI32U => write!(f, "i64.extend_i32_u"),
+            Instruction::LocalGet(l) => write!(f, "local.get {}", l),
+            Instruction::LocalSet(l) => write!(f, "local.set {}", l),
+        }
+    }
+}
+
+/// A module exporting a single function that takes no parameters and
+/// returns an `i64`. The function has `locals` locals, all `i64`.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Module {
+    pub export: String,
+    pub locals: u32,
+    pub body: Vec<Instruction>,
+}
+
+/// Prints the module in the text format.
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "(module")?;
+        write!(f, "  (func (export \"{}\") (result i64)", self.export)?;
+        if self.locals > 0 {
+            write!(f, "\n    (local{})", " i64".repeat(self.locals as usize))?;
+        }
+        for i in &self.body {
+            write!(f, "\n    {}", i)?;
+        }
+        writeln!(f, "))")
+    }
+}
+
+const MAGIC: &[u8] = b"\0asm";
+const VERSION: [u8; 4] = [1, 0, 0, 0];
+
+const TYPE_SECTION: u8 = 1;
+const FUNCTION_SECTION: u8 = 3;
+const EXPORT_SECTION: u8 = 7;
+const CODE_SECTION: u8 = 10;
+
+const FUNC_TYPE: u8 = 0x60;
+const I64: u8 = 0x7e;
+const EXPORT_FUNC: u8 = 0x00;
+const END: u8 = 0x0b;
+
+impl Module {
+    /// Encodes the module in the binary format.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = [MAGIC, &VERSION].concat();
+
+        // One type, () -> i64.
+        section(&mut bytes, TYPE_SECTION, &[1, FUNC_TYPE, 0, 1, I64]);
+
+        // One function, of type 0.
+        section(&mut bytes, FUNCTION_SECTION, &[1, 0]);
+
+        let mut export = vec![1];
+        name(&mut export, &self.export);
+        export.extend(&[EXPORT_FUNC, 0]);
+        section(&mut bytes, EXPORT_SECTION, &export);
+
+        let mut body = match self.locals {
+            0 => vec![0],
+            n => {
+                let mut locals = vec![1];
+                unsigned(&mut locals, n as u64);
+                locals.push(I64);
+                locals
+            }
+        };
+        for i in &self.body {
+            body.push(i.opcode());
+            match *i {
+                Instruction::Const(n) => signed(&mut body, n),
+                Instruction::LocalGet(l) | Instruction::LocalSet(l) => {
+                    unsigned(&mut body, l as u64)
+                }
+                _ => {}
+            }
+        }
+        body.push(END);
+        let mut code = vec![1];
+        unsigned(&mut code, body.len() as u64);
+        code.extend(body);
+        section(&mut bytes, CODE_SECTION, &code);
+
+        bytes
+    }
+}
+
+fn section(bytes: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    bytes.push(id);
+    unsigned(bytes, contents.len() as u64);
+    bytes.extend(contents);
+}
+
+fn name(bytes: &mut Vec<u8>, s: &str) {
+    unsigned(bytes, s.len() as u64);
+    bytes.extend(s.as_bytes());
+}
+
+/// Unsigned LEB128.
+fn unsigned(bytes: &mut Vec<u8>, mut n: u64) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        match n {
+            0 => return bytes.push(byte),
+            _ => bytes.push(byte | 0x80),
+        }
+    }
+}
+
+/// Signed LEB128.
+fn signed(bytes: &mut Vec<u8>, mut n: i64) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        // Done once the rest is all sign bits, and the sign bit of the
+        // last byte agrees with them.
+        match (n == 0 && byte & 0x40 == 0) || (n == -1 && byte & 0x40 != 0) {
+            true => return bytes.push(byte),
+            false => bytes.push(byte | 0x80),
+        }
+    }
+}
+
+/// Lowers `f` into a module exporting it under its name.
+///
+/// Values are computed in the order of the IR, and each one is used by
+/// the next instruction that needs it, so it can stay on the stack. The
+/// exception is a right operand computed before its left one, which is
+/// kept in a local until the left one is on the stack.
+///
+/// `i64.div_s` traps on a division by zero or an overflowing division.
+/// Other arithmetic wraps.
+pub fn lower(f: &Function) -> Module {
+    let mut locals = BTreeMap::new();
+    for i in &f.instructions {
+        if let Ir::Binary(_, _, a, b) = *i {
+            if b < a {
+                let l = locals.len() as u32;
+                locals.insert(b, l);
+            }
+        }
+    }
+
+    let mut body = vec![];
+    let get = |body: &mut Vec<Instruction>, v: Value| {
+        if let Some(l) = locals.get(&v) {
+            body.push(Instruction::LocalGet(*l));
+        }
+    };
+    for i in &f.instructions {
+        match *i {
+            Ir::Const(_, n) => body.push(Instruction::Const(n)),
+            Ir::Unary(_, op, a) => {
+                get(&mut body, a);
+                body.extend(match op {
+                    // 0 - a would need the 0 below a on the stack.
+                    UnaryOp::Neg => vec![Instruction::Const(-1), Instruction::Mul],
+                    UnaryOp::Not => vec![Instruction::Const(-1), Instruction::Xor],
+                    UnaryOp::LogicalNot => vec![Instruction::Eqz, Instruction::ExtendI32U],
+                });
+            }
+            Ir::Binary(_, op, a, b) => {
+                get(&mut body, a);
+                get(&mut body, b);
+                body.push(match op {
+                    BinaryOp::Add => Instruction::Add,
+                    BinaryOp::Sub => Instruction::Sub,
+                    BinaryOp::Mul => Instruction::Mul,
+                    BinaryOp::Div => Instruction::DivS,
+                });
+            }
+        }
+        if let Some(l) = locals.get(&i.defines()) {
+            body.push(Instruction::LocalSet(*l));
+        }
+    }
+
+    Module {
+        export: f.name.clone(),
+        locals: locals.len() as u32,
+        body,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::wasm::Instruction::*;
+    use crate::compiler::wasm::*;
+    use crate::ir;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Function {
+        ir::lower(&Parser::new(Lexer::new(input)).parse().unwrap()[0])
+    }
+
+    #[test]
+    fn test_lower() {
+        let tests = vec![
+            ("+1", vec![Const(1)]),
+            ("1-2", vec![Const(1), Const(2), Sub]),
+            ("-(8/2)", vec![Const(8), Const(2), DivS, Const(-1), Mul]),
+            (
+                "~3*!0",
+                vec![Const(3), Const(-1), Xor, Const(0), Eqz, ExtendI32U, Mul],
+            ),
+            (
+                // The right operand needs more values, so it comes first.
+                "1-2*3",
+                vec![
+                    Const(2),
+                    Const(3),
+                    Mul,
+                    LocalSet(0),
+                    Const(1),
+                    LocalGet(0),
+                    Sub,
+                ],
+            ),
+        ];
+
+        for (input, want) in tests {
+            assert_eq!(want, lower(&parse(input)).body);
+        }
+    }
+
+    #[test]
+    fn test_wat() {
+        let want = r#"(module
+  (func (export "main") (result i64)
+    i64.const 2
+    i64.const 3
+    i64.add))
+"#;
+
+        assert_eq!(want, lower(&parse("2+3")).to_string());
+    }
+
+    #[test]
+    fn test_wat_locals() {
+        let want = r#"(module
+  (func (export "main") (result i64)
+    (local i64)
+    i64.const 3
+    i64.const 4
+    i64.mul
+    local.set 0
+    i64.const 2
+    local.get 0
+    i64.div_s))
+"#;
+
+        assert_eq!(want, lower(&parse("2/(3*4)")).to_string());
+    }
+
+    #[test]
+    fn test_leb128() {
+        let tests: Vec<(i64, Vec<u8>)> = vec![
+            (0, vec![0x00]),
+            (63, vec![0x3f]),
+            (64, vec![0xc0, 0x00]),
+            (-1, vec![0x7f]),
+            (-64, vec![0x40]),
+            (-65, vec![0xbf, 0x7f]),
+            (624485, vec![0xe5, 0x8e, 0x26]),
+            (
+                i64::MIN,
+                vec![0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x7f],
+            ),
+        ];
+
+        for (n, want) in tests {
+            let mut got = vec![];
+            signed(&mut got, n);
+            assert_eq!(want, got);
+        }
+
+        let mut got = vec![];
+        unsigned(&mut got, 624485);
+        assert_eq!(vec![0xe5, 0x8e, 0x26], got);
+    }
+
+    #[test]
+    fn test_encode() {
+        let want: Vec<u8> = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7e, // type
+            0x03, 0x02, 0x01, 0x00, // function
+            0x07, 0x08, 0x01, 0x04, b'm', b'a', b'i', b'n', 0x00, 0x00, // export
+            0x0a, 0x0a, 0x01, 0x08, 0x00, 0x42, 0x02, 0x42, 0xc0, 0x00, 0x7c, 0x0b, // code
+        ];
+
+        assert_eq!(want, lower(&parse("2+64")).encode());
+
+        let want: Vec<u8> = vec![
+            0x0a, 0x12, 0x01, 0x10, 0x01, 0x01, 0x7e, // code, with one local
+            0x42, 0x03, 0x42, 0x04, 0x7e, 0x21, 0x00, 0x42, 0x02, 0x20, 0x00, 0x7f, 0x0b,
+        ];
+        assert!(lower(&parse("2/(3*4)")).encode().ends_with(&want));
+    }
+}
@@ -0,0 +1,365 @@
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub enum Register {
+    Rax,
+    Rcx,
+    Rdx,
+    Rbx,
+    Rsp,
+    Rbp,
+    Rsi,
+    Rdi,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+}
+
+impl Register {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Register::Rax => "rax",
+            Register::Rcx => "rcx",
+            Register::Rdx => "rdx",
+            Register::Rbx => "rbx",
+            Register::Rsp => "rsp",
+            Register::Rbp => "rbp",
+            Register::Rsi => "rsi",
+            Register::Rdi => "rdi",
+            Register::R8 => "r8",
+            Register::R9 => "r9",
+            Register::R10 => "r10",
+            Register::R11 => "r11",
+            Register::R12 => "r12",
+            Register::R13 => "r13",
+            Register::R14 => "r14",
+            Register::R15 => "r15",
+        }
+    }
+
+    /// Name of the lowest byte of the register.
+    pub fn low_name(&self) -> &'static str {
+        match *self {
+            Register::Rax => "al",
+            Register::Rcx => "cl",
+            Register::Rdx => "dl",
+            Register::Rbx => "bl",
+            Register::Rsp => "spl",
+            Register::Rbp => "bpl",
+            Register::Rsi => "sil",
+            Register::Rdi => "dil",
+            Register::R8 => "r8b",
+            Register::R9 => "r9b",
+            Register::R10 => "r10b",
+            Register::R11 => "r11b",
+            Register::R12 => "r12b",
+            Register::R13 => "r13b",
+            Register::R14 => "r14b",
+            Register::R15 => "r15b",
+        }
+    }
+}
+
+/// A memory address of the form `[base + index * scale + displacement]`.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Address {
+    pub base: Register,
+    pub index: Option<(Register, u8)>,
+    pub displacement: i32,
+}
+
+impl Address {
+    pub fn new(base: Register, displacement: i32) -> Self {
+        Address {
+            base,
+            index: None,
+            displacement,
+        }
+    }
+
+    fn registers(&self) -> Vec<Register> {
+        match self.index {
+            Some((index, _)) => vec![self.base, index],
+            None => vec![self.base],
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Operand {
+    Register(Register),
+    /// The lowest byte of a register.
+    Byte(Register),
+    Immediate(i64),
+    /// A quadword in memory.
+    Memory(Address),
+}
+
+impl Operand {
+    /// Registers the operand reads when used as a source, including the
+    /// ones that form an address.
+    fn reads(&self) -> Vec<Register> {
+        match *self {
+            Operand::Register(r) | Operand::Byte(r) => vec![r],
+            Operand::Immediate(_) => vec![],
+            Operand::Memory(a) => a.registers(),
+        }
+    }
+
+    /// Registers the operand reads when used as a destination.
+    fn reads_as_destination(&self) -> Vec<Register> {
+        match *self {
+            // Writing the low byte keeps the rest of the register.
+            Operand::Byte(r) => vec![r],
+            Operand::Memory(a) => a.registers(),
+            _ => vec![],
+        }
+    }
+
+    fn register(&self) -> Option<Register> {
+        match *self {
+            Operand::Register(r) | Operand::Byte(r) => Some(r),
+            _ => None,
+        }
+    }
+
+    pub fn mentions(&self, r: Register) -> bool {
+        self.reads().contains(&r)
+    }
+}
+
+/// Flags a conditional jump tests after a `cmp`, as signed comparisons, or
+/// after arithmetic for `O`.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Condition {
+    O,
+    E,
+    Ne,
+    Le,
+    Ge,
+}
+
+impl Condition {
+    /// The low nibble of the `jcc` opcode.
+    pub fn code(&self) -> u8 {
+        match *self {
+            Condition::O => 0x0,
+            Condition::E => 0x4,
+            Condition::Ne => 0x5,
+            Condition::Ge => 0xd,
+            Condition::Le => 0xe,
+        }
+    }
+}
+
+/// The subset of x86-64 the compiler emits. Two-operand instructions take
+/// the destination first.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Instruction {
+    Push(Operand),
+    Pop(Operand),
+    Mov(Operand, Operand),
+    /// Stores the lowest byte of a register.
+    MovByte(Address, Register),
+    Movzx(Operand, Operand),
+    Lea(Register, Address),
+    Add(Operand, Operand),
+    Sub(Operand, Operand),
+    Imul(Operand, Operand),
+    Shl(Operand, Operand),
+    Cmp(Operand, Operand),
+    Sete(Operand),
+    Neg(Operand),
+    Not(Operand),
+    Cqo,
+    Idiv(Operand),
+    Ret,
+    /// Marks the position of a label; encodes to nothing.
+    Label(&'static str),
+    /// Jumps to a label if the last comparison meets the condition.
+    J(Condition, &'static str),
+    Call(&'static str),
+    /// Calls the kernel with the number in rax and arguments in rdi, rsi,
+    /// rdx, r10, r8 and r9.
+    Syscall,
+}
+
+impl Instruction {
+    pub fn mnemonic(&self) -> &'static str {
+        match *self {
+            Instruction::Push(_) => "push",
+            Instruction::Pop(_) => "pop",
+            Instruction::Mov(_, _) | Instruction::MovByte(_, _) => "mov",
+            Instruction::Movzx(_, _) => "movzx",
+            Instruction::Lea(_, _) => "lea",
+            Instruction::Add(_, _) => "add",
+            Instruction::Sub(_, _) => "sub",
+            Instruction::Imul(_, _) => "imul",
+            Instruction::Shl(_, _) => "shl",
+            Instruction::Cmp(_, _) => "cmp",
+            Instruction::Sete(_) => "sete",
+            Instruction::Neg(_) => "neg",
+            Instruction::Not(_) => "not",
+            Instruction::Cqo => "cqo",
+            Instruction::Idiv(_) => "idiv",
+            Instruction::Ret => "ret",
+            Instruction::Label(_) => "",
+            Instruction::J(Condition::O, _) => "jo",
+            Instruction::J(Condition::E, _) => "je",
+            Instruction::J(Condition::Ne, _) => "jne",
+            Instruction::J(Condition::Le, _) => "jle",
+            Instruction::J(Condition::Ge, _) => "jge",
+            Instruction::Call(_) => "call",
+            Instruction::Syscall => "syscall",
+        }
+    }
+
+    /// Operands in Intel order, destination first.
+    pub fn operands(&self) -> Vec<Operand> {
+        match *self {
+            Instruction::Push(a)
+            | Instruction::Pop(a)
+            | Instruction::Sete(a)
+            | Instruction::Neg(a)
+            | Instruction::Not(a)
+            | Instruction::Idiv(a) => vec![a],
+            Instruction::Mov(a, b)
+            | Instruction::Movzx(a, b)
+            | Instruction::Add(a, b)
+            | Instruction::Sub(a, b)
+            | Instruction::Imul(a, b)
+            | Instruction::Shl(a, b)
+            | Instruction::Cmp(a, b) => vec![a, b],
+            Instruction::Lea(r, address) => vec![Operand::Register(r), Operand::Memory(address)],
+            Instruction::MovByte(address, r) => vec![Operand::Memory(address), Operand::Byte(r)],
+            Instruction::Cqo
+            | Instruction::Ret
+            | Instruction::Label(_)
+            | Instruction::J(_, _)
+            | Instruction::Call(_)
+            | Instruction::Syscall => vec![],
+        }
+    }
+
+    /// Registers whose value the instruction depends on.
+    pub fn reads(&self) -> Vec<Register> {
+        let mut registers = match *self {
+            Instruction::Push(a) => vec![a.reads(), vec![Register::Rsp]],
+            Instruction::Pop(a) => vec![a.reads_as_destination(), vec![Register::Rsp]],
+            Instruction::Mov(a, b) | Instruction::Movzx(a, b) => {
+                vec![a.reads_as_destination(), b.reads()]
+            }
+            Instruction::Lea(_, address) => vec![address.registers()],
+            Instruction::MovByte(address, r) => vec![address.registers(), vec![r]],
+            Instruction::Add(a, b)
+            | Instruction::Sub(a, b)
+            | Instruction::Imul(a, b)
+            | Instruction::Shl(a, b)
+            | Instruction::Cmp(a, b) => vec![a.reads(), b.reads()],
+            Instruction::Sete(a) => vec![a.reads_as_destination()],
+            Instruction::Neg(a) | Instruction::Not(a) => vec![a.reads()],
+            Instruction::Cqo => vec![vec![Register::Rax]],
+            Instruction::Idiv(a) => vec![a.reads(), vec![Register::Rax, Register::Rdx]],
+            Instruction::Ret => vec![vec![Register::Rax, Register::Rsp]],
+            Instruction::Label(_) | Instruction::J(_, _) => vec![],
+            Instruction::Call(_) => vec![vec![Register::Rsp]],
+            Instruction::Syscall => vec![vec![
+                Register::Rax,
+                Register::Rdi,
+                Register::Rsi,
+                Register::Rdx,
+                Register::R10,
+                Register::R8,
+                Register::R9,
+            ]],
+        }
+        .concat();
+
+        registers.sort();
+        registers.dedup();
+        registers
+    }
+
+    /// Registers the instruction assigns.
+    pub fn writes(&self) -> Vec<Register> {
+        let registers = match *self {
+            Instruction::Push(_) | Instruction::Ret | Instruction::Call(_) => {
+                vec![Some(Register::Rsp)]
+            }
+            Instruction::Pop(a) => vec![a.register(), Some(Register::Rsp)],
+            Instruction::Mov(a, _)
+            | Instruction::Movzx(a, _)
+            | Instruction::Add(a, _)
+            | Instruction::Sub(a, _)
+            | Instruction::Imul(a, _)
+            | Instruction::Shl(a, _)
+            | Instruction::Sete(a)
+            | Instruction::Neg(a)
+            | Instruction::Not(a) => vec![a.register()],
+            Instruction::Lea(r, _) => vec![Some(r)],
+            Instruction::Cmp(_, _)
+            | Instruction::MovByte(_, _)
+            | Instruction::Label(_)
+            | Instruction::J(_, _) => vec![],
+            Instruction::Cqo => vec![Some(Register::Rdx)],
+            Instruction::Idiv(_) => vec![Some(Register::Rax), Some(Register::Rdx)],
+            // The kernel returns in rax and clobbers rcx and r11.
+            Instruction::Syscall => vec![
+                Some(Register::Rax),
+                Some(Register::Rcx),
+                Some(Register::R11),
+            ],
+        };
+
+        registers.into_iter().flatten().collect()
+    }
+}
+
+/// A function ready to be printed or assembled.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Assembly {
+    pub symbol: String,
+    pub instructions: Vec<Instruction>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::x86::*;
+
+    #[test]
+    fn test_reads_and_writes() {
+        let tests = vec![
+            (
+                Instruction::Pop(Operand::Register(Register::Rdi)),
+                vec![Register::Rsp],
+                vec![Register::Rdi, Register::Rsp],
+            ),
+            (
+                Instruction::Mov(
+                    Operand::Memory(Address::new(Register::Rbp, -8)),
+                    Operand::Register(Register::Rcx),
+                ),
+                vec![Register::Rcx, Register::Rbp],
+                vec![],
+            ),
+            (
+                Instruction::Sete(Operand::Byte(Register::Rsi)),
+                vec![Register::Rsi],
+                vec![Register::Rsi],
+            ),
+            (
+                Instruction::Idiv(Operand::Register(Register::Rdi)),
+                vec![Register::Rax, Register::Rdx, Register::Rdi],
+                vec![Register::Rax, Register::Rdx],
+            ),
+        ];
+
+        for (instruction, reads, writes) in tests {
+            assert_eq!(reads, instruction.reads());
+            assert_eq!(writes, instruction.writes());
+        }
+    }
+}
@@ -0,0 +1,495 @@
+use crate::ast::{Expr, Infix, Literal, Prefix, Statement};
+use crate::error::RuntimeError;
+use crate::evaluator::Evaluator;
+use crate::object::Object;
+use std::fmt;
+
+/// Literals picked more often than chance would, because they sit on the
+/// edges of overflow and division.
+const INTERESTING: [i64; 9] = [0, 1, 2, 3, 10, 1 << 32, 1 << 62, i64::MAX, i64::MIN];
+
+/// What running an expression comes to.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Outcome {
+    Value(i64),
+    Error(RuntimeError),
+    /// The program did not finish the way any calculator program should,
+    /// e.g. it was killed by a signal. Only `Native` runs programs.
+    #[cfg_attr(
+        not(all(target_os = "linux", target_arch = "x86_64")),
+        allow(dead_code)
+    )]
+    Crash(String),
+}
+
+impl From<Result<Object, RuntimeError>> for Outcome {
+    fn from(result: Result<Object, RuntimeError>) -> Self {
+        match result {
+            Ok(Object::Int(i)) => Outcome::Value(i),
+            Err(e) => Outcome::Error(e),
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Value(i) => write!(f, "{}", i),
+            Outcome::Error(e) => write!(f, "{}", e),
+            Outcome::Crash(s) => write!(f, "a crash ({})", s),
+        }
+    }
+}
+
+/// Generates random well-formed expressions. The same seed always gives the
+/// same expressions, so a failure can be reproduced.
+#[derive(Debug)]
+pub struct Generator {
+    state: u64,
+}
+
+impl Generator {
+    pub fn new(seed: u64) -> Self {
+        // xorshift never leaves 0.
+        Self {
+            state: seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1,
+        }
+    }
+
+    /// xorshift64*.
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    /// An expression at most `depth` levels deep.
+    pub fn expr(&mut self, depth: usize) -> Expr {
+        if depth <= 1 {
+            return self.literal();
+        }
+
+        match self.below(8) {
+            0 | 1 => self.literal(),
+            2 | 3 => {
+                let prefix = match self.below(4) {
+                    0 => Prefix::Plus,
+                    1 => Prefix::Minus,
+                    2 => Prefix::BitNot,
+                    _ => Prefix::Not,
+                };
+                Expr::Prefix(prefix, Box::new(self.expr(depth - 1)))
+            }
+            _ => {
+                let infix = match self.below(4) {
+                    0 => Infix::Plus,
+                    1 => Infix::Minus,
+                    2 => Infix::Multiply,
+                    _ => Infix::Divide,
+                };
+                let x = self.expr(depth - 1);
+                let y = self.expr(depth - 1);
+                Expr::Infix(infix, Box::new(x), Box::new(y))
+            }
+        }
+    }
+
+    fn literal(&mut self) -> Expr {
+        let i = match self.below(6) {
+            0 => INTERESTING[self.below(INTERESTING.len() as u64) as usize],
+            1 => (self.next() >> 1) as i64,
+            _ => self.below(100) as i64,
+        };
+        match self.below(4) {
+            0 => Expr::Literal(Literal::Int(i.wrapping_neg())),
+            _ => Expr::Literal(Literal::Int(i)),
+        }
+    }
+}
+
+/// Expressions smaller than `ex`: its operands on their own first, then
+/// `ex` with one part shrunk.
+pub fn shrink(ex: &Expr) -> Vec<Expr> {
+    match ex {
+        Expr::Literal(Literal::Int(i)) => {
+            // Toward zero by magnitude, keeping the sign.
+            let mut smaller: Vec<i64> = vec![0, i.signum(), i / 2];
+            smaller.retain(|j| j.unsigned_abs() < i.unsigned_abs());
+            smaller.dedup();
+            smaller
+                .into_iter()
+                .map(|j| Expr::Literal(Literal::Int(j)))
+                .collect()
+        }
+        Expr::Prefix(p, x) => {
+            let mut smaller = vec![*x.clone()];
+            for x in shrink(x) {
+                smaller.push(Expr::Prefix(p.clone(), Box::new(x)));
+            }
+            smaller
+        }
+        Expr::Infix(i, x, y) => {
+            let mut smaller = vec![*x.clone(), *y.clone()];
+            for x in shrink(x) {
+                smaller.push(Expr::Infix(i.clone(), Box::new(x), y.clone()));
+            }
+            for y in shrink(y) {
+                smaller.push(Expr::Infix(i.clone(), x.clone(), Box::new(y)));
+            }
+            smaller
+        }
+    }
+}
+
+/// Shrinks `ex` for as long as some smaller expression still `fails`.
+pub fn minimize<F: FnMut(&Expr) -> bool>(mut ex: Expr, mut fails: F) -> Expr {
+    while let Some(smaller) = shrink(&ex).into_iter().find(|s| fails(s)) {
+        ex = smaller;
+    }
+    ex
+}
+
+pub fn evaluate(ex: &Expr) -> Outcome {
+    Evaluator::new()
+        .eval(vec![Statement::Expression(ex.clone())])
+        .into()
+}
+
+/// An expression on which the evaluator and another way of running it
+/// disagree.
+#[derive(PartialEq, Debug)]
+pub struct Mismatch {
+    pub expr: Expr,
+    pub want: Outcome,
+    pub got: Outcome,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} evaluates to {} but runs to {}",
+            self.expr, self.want, self.got
+        )
+    }
+}
+
+/// Compares `run` with the evaluator on `ex`. When they disagree, the
+/// counterexample is minimized before it is reported.
+pub fn check<F: FnMut(&Expr) -> Outcome>(ex: &Expr, mut run: F) -> Result<(), Mismatch> {
+    let mut disagrees = |ex: &Expr| evaluate(ex) != run(ex);
+    if !disagrees(ex) {
+        return Ok(());
+    }
+
+    let expr = minimize(ex.clone(), disagrees);
+    Err(Mismatch {
+        want: evaluate(&expr),
+        got: run(&expr),
+        expr,
+    })
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+pub use native::Native;
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+mod native {
+    use crate::ast::{Expr, Statement};
+    use crate::compiler::{Compiler, Target, TRAP_STATUS};
+    use crate::differential::Outcome;
+    use crate::error::RuntimeError;
+    use crate::ir::lower;
+    use crate::optimizer::optimize;
+    use std::process::Command;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static PROGRAMS: AtomicUsize = AtomicUsize::new(0);
+
+    /// Assembles x86-64 programs with gcc and runs them, so the assembler and
+    /// the CPU have their say on the generated code.
+    #[derive(Debug)]
+    pub struct Native {
+        compiler: Compiler,
+        optimize: bool,
+    }
+
+    impl Native {
+        /// Builds checked programs that print their result with `compiler`, or
+        /// gives None when gcc cannot be run.
+        pub fn new(compiler: Compiler, optimize: bool) -> Option<Self> {
+            let gcc = Command::new("gcc").arg("--version").output().ok()?;
+            if !gcc.status.success() {
+                return None;
+            }
+
+            let compiler = compiler
+                .target(Target::X86_64)
+                .check_division(true)
+                .check_overflow(true)
+                .define_traps(true)
+                .print_result(true);
+            Some(Self { compiler, optimize })
+        }
+
+        /// Panics when gcc rejects the assembly, which is a bug of its own.
+        pub fn run(&self, ex: &Expr) -> Outcome {
+            let mut program = vec![Statement::Expression(ex.clone())];
+            if self.optimize {
+                program = optimize(program);
+            }
+            let source = self.compiler.compile_function(&lower(&program[0]));
+
+            let path = std::env::temp_dir().join(format!(
+                "calculator-differential-{}-{}",
+                std::process::id(),
+                PROGRAMS.fetch_add(1, Ordering::SeqCst)
+            ));
+            let assembly = path.with_extension("s");
+            std::fs::write(&assembly, source).unwrap();
+            let gcc = Command::new("gcc")
+                .arg("-o")
+                .arg(&path)
+                .arg(&assembly)
+                .output()
+                .unwrap();
+            std::fs::remove_file(&assembly).unwrap();
+            assert!(
+                gcc.status.success(),
+                "gcc rejected {}: {}",
+                ex,
+                String::from_utf8_lossy(&gcc.stderr)
+            );
+
+            let output = Command::new(&path).output();
+            let _ = std::fs::remove_file(&path);
+            let output = match output {
+                Ok(output) => output,
+                Err(e) => return Outcome::Crash(e.to_string()),
+            };
+
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            match output.status.code() {
+                Some(0) => match stdout.trim_end().parse() {
+                    Ok(i) => Outcome::Value(i),
+                    Err(_) => Outcome::Crash(format!("printed {:?}", stdout)),
+                },
+                Some(status) if status as i64 == TRAP_STATUS => {
+                    let errors = [RuntimeError::DivideByZero, RuntimeError::Overflow];
+                    match errors.iter().find(|e| stderr.trim_end() == e.to_string()) {
+                        Some(e) => Outcome::Error(e.clone()),
+                        None => Outcome::Crash(format!("reported {:?}", stderr)),
+                    }
+                }
+                _ => Outcome::Crash(output.status.to_string()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{Expr, Infix, Literal, Prefix, Statement};
+    use crate::bytecode;
+    use crate::differential::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::vm::Vm;
+
+    fn parse(input: &str) -> Expr {
+        match Parser::new(Lexer::new(input)).parse().unwrap().remove(0) {
+            Statement::Expression(ex) => ex,
+        }
+    }
+
+    /// Every configuration the x86-64 backend has.
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    fn compilers() -> Vec<(crate::compiler::Compiler, bool)> {
+        use crate::compiler::{Allocation, Compiler};
+
+        let mut compilers = vec![];
+        for allocation in &[Allocation::Stack, Allocation::Registers] {
+            for optimize in &[false, true] {
+                let compiler = Compiler::new().allocation(*allocation).peephole(*optimize);
+                compilers.push((compiler, *optimize));
+            }
+        }
+        compilers
+    }
+
+    #[test]
+    fn test_generator() {
+        let mut a = Generator::new(7);
+        let mut b = Generator::new(7);
+
+        for _ in 0..100 {
+            let ex = a.expr(6);
+            assert_eq!(b.expr(6), ex);
+            // Well-formed, so printing and parsing it again changes nothing.
+            assert_eq!(ex, parse(&ex.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_shrink() {
+        let tests = vec![
+            ("0", vec![]),
+            ("1", vec!["0"]),
+            ("-1", vec!["0"]),
+            ("7", vec!["0", "1", "3"]),
+            ("-7", vec!["0", "-1", "-3"]),
+            (
+                "-9223372036854775808",
+                vec!["0", "-1", "-4611686018427387904"],
+            ),
+        ];
+
+        for (input, want) in tests {
+            let want: Vec<Expr> = want.into_iter().map(parse).collect();
+            assert_eq!(want, shrink(&parse(input)));
+        }
+    }
+
+    #[test]
+    fn test_minimize() {
+        let tests = vec![
+            ("1 + 2 * (3 / 4)", "0 / 0"),
+            ("-(7 - 100 / (5 + 6)) * 2", "0 / 0"),
+            ("(1 + 2) * 3", "(1 + 2) * 3"),
+        ];
+
+        fn divides(ex: &Expr) -> bool {
+            match ex {
+                Expr::Literal(_) => false,
+                Expr::Prefix(_, x) => divides(x),
+                Expr::Infix(Infix::Divide, _, _) => true,
+                Expr::Infix(_, x, y) => divides(x) || divides(y),
+            }
+        }
+
+        for (input, want) in tests {
+            let ex = parse(input);
+            let got = match divides(&ex) {
+                true => minimize(ex, divides),
+                false => ex,
+            };
+            assert_eq!(parse(want), got);
+        }
+    }
+
+    /// Evaluates like the evaluator, except that arithmetic wraps around.
+    fn wrapping(ex: &Expr) -> Outcome {
+        fn value(ex: &Expr) -> Result<i64, RuntimeError> {
+            Ok(match ex {
+                Expr::Literal(Literal::Int(i)) => *i,
+                Expr::Prefix(Prefix::Plus, x) => value(x)?,
+                Expr::Prefix(Prefix::Minus, x) => value(x)?.wrapping_neg(),
+                Expr::Prefix(Prefix::BitNot, x) => !value(x)?,
+                Expr::Prefix(Prefix::Not, x) => (value(x)? == 0) as i64,
+                Expr::Infix(i, x, y) => {
+                    let (x, y) = (value(x)?, value(y)?);
+                    match i {
+                        Infix::Plus => x.wrapping_add(y),
+                        Infix::Minus => x.wrapping_sub(y),
+                        Infix::Multiply => x.wrapping_mul(y),
+                        Infix::Divide if y == 0 => return Err(RuntimeError::DivideByZero),
+                        Infix::Divide => x.wrapping_div(y),
+                    }
+                }
+            })
+        }
+        value(ex).map(Object::Int).into()
+    }
+
+    #[test]
+    fn test_check() {
+        let tests = vec![
+            ("1 + 2 * 3", Ok(())),
+            ("10 / (5 - 5)", Ok(())),
+            (
+                "1 + (2 * (9223372036854775807 + 3))",
+                Err(Mismatch {
+                    expr: parse("9223372036854775807 + 1"),
+                    want: Outcome::Error(RuntimeError::Overflow),
+                    got: Outcome::Value(i64::MIN),
+                }),
+            ),
+        ];
+
+        for (input, want) in tests {
+            assert_eq!(want, check(&parse(input), wrapping));
+        }
+
+        let mismatch = check(&Expr::Literal(Literal::Int(5)), |_| Outcome::Value(6));
+        assert_eq!(
+            "0 evaluates to 0 but runs to 6",
+            mismatch.unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_vm_matches_evaluator() {
+        let vm = |ex: &Expr| -> Outcome {
+            let program = vec![Statement::Expression(ex.clone())];
+            Vm::new().run(&bytecode::compile(&program)).into()
+        };
+
+        let mut generator = Generator::new(0);
+        for _ in 0..1000 {
+            if let Err(mismatch) = check(&generator.expr(6), vm) {
+                panic!("{}", mismatch);
+            }
+        }
+    }
+
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    fn test_jit_matches_evaluator() {
+        use crate::ir::lower;
+        use crate::jit::JitFunction;
+        use crate::optimizer::optimize;
+
+        for (n, (compiler, optimized)) in compilers().into_iter().enumerate() {
+            let mut generator = Generator::new(n as u64);
+            for _ in 0..300 {
+                let jit = |ex: &Expr| -> Outcome {
+                    let compiler = compiler.clone();
+                    let mut program = vec![Statement::Expression(ex.clone())];
+                    if optimized {
+                        program = optimize(program);
+                    }
+                    let f = JitFunction::new(compiler, &lower(&program[0])).unwrap();
+                    f.call().map(Object::Int).into()
+                };
+
+                if let Err(mismatch) = check(&generator.expr(6), jit) {
+                    panic!("{}", mismatch);
+                }
+            }
+        }
+    }
+
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    fn test_native_matches_evaluator() {
+        for (n, (compiler, optimized)) in compilers().into_iter().enumerate() {
+            let native = match Native::new(compiler, optimized) {
+                Some(native) => native,
+                None => return,
+            };
+
+            let mut generator = Generator::new(n as u64);
+            for _ in 0..15 {
+                if let Err(mismatch) = check(&generator.expr(5), |ex| native.run(ex)) {
+                    panic!("{}", mismatch);
+                }
+            }
+        }
+    }
+}
@@ -5,6 +5,7 @@ pub enum ParseError {
     FoundIllegalToken,
     FoundUnexpectedToken,
     FoundUnterminatedParentheses,
+    FoundOutOfRangeInteger,
 }
 
 impl fmt::Display for ParseError {
@@ -15,6 +16,7 @@ impl fmt::Display for ParseError {
             ParseError::FoundUnterminatedParentheses => {
                 write!(f, "Found an unterminated parentheses.")
             }
+            ParseError::FoundOutOfRangeInteger => write!(f, "Found an out of range integer."),
         }
     }
 }
@@ -23,6 +25,7 @@ impl fmt::Display for ParseError {
 pub enum RuntimeError {
     DivideByZero,
     FoundNoProgram,
+    Overflow,
 }
 
 impl fmt::Display for RuntimeError {
@@ -30,6 +33,32 @@ impl fmt::Display for RuntimeError {
         match self {
             RuntimeError::DivideByZero => write!(f, "Divide a number by 0."),
             RuntimeError::FoundNoProgram => write!(f, "Found no program."),
+            RuntimeError::Overflow => write!(f, "Found an integer overflow."),
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum BytecodeError {
+    BadMagic,
+    UnsupportedVersion,
+    Truncated,
+    TrailingData,
+    ChecksumMismatch,
+    InvalidCode,
+}
+
+impl fmt::Display for BytecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BytecodeError::BadMagic => write!(f, "Found no bytecode header."),
+            BytecodeError::UnsupportedVersion => {
+                write!(f, "Found an unsupported bytecode version.")
+            }
+            BytecodeError::Truncated => write!(f, "Found truncated bytecode."),
+            BytecodeError::TrailingData => write!(f, "Found data after the bytecode checksum."),
+            BytecodeError::ChecksumMismatch => write!(f, "Found a bytecode checksum mismatch."),
+            BytecodeError::InvalidCode => write!(f, "Found invalid bytecode."),
         }
     }
 }
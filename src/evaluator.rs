@@ -5,13 +5,19 @@ use crate::object::*;
 #[derive(Debug)]
 pub struct Evaluator {}
 
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Evaluator {
     pub fn new() -> Self {
         Evaluator {}
     }
 
     pub fn eval(&mut self, program: Program) -> Result<Object, RuntimeError> {
-        for stmt in program {
+        if let Some(stmt) = program.into_iter().next() {
             return self.eval_statement(stmt);
         }
         Err(RuntimeError::FoundNoProgram)
@@ -28,7 +34,7 @@ impl Evaluator {
 
             Expr::Prefix(prefix, right_expression) => match self.eval_expression(*right_expression)
             {
-                Ok(right) => Ok(self.eval_prefix_expression(prefix, right)),
+                Ok(right) => self.eval_prefix_expression(prefix, right),
                 Err(e) => Err(e),
             },
 
@@ -43,15 +49,37 @@ impl Evaluator {
         }
     }
 
-    fn eval_prefix_expression(&mut self, prefix: Prefix, right: Object) -> Object {
+    fn eval_prefix_expression(
+        &mut self,
+        prefix: Prefix,
+        right: Object,
+    ) -> Result<Object, RuntimeError> {
         match prefix {
+            Prefix::Plus => Ok(right),
             Prefix::Minus => self.eval_minus_prefix(right),
+            Prefix::BitNot => Ok(self.eval_bit_not_prefix(right)),
+            Prefix::Not => Ok(self.eval_not_prefix(right)),
+        }
+    }
+
+    fn eval_minus_prefix(&mut self, right: Object) -> Result<Object, RuntimeError> {
+        match right {
+            Object::Int(value) => value
+                .checked_neg()
+                .map(Object::Int)
+                .ok_or(RuntimeError::Overflow),
+        }
+    }
+
+    fn eval_bit_not_prefix(&mut self, right: Object) -> Object {
+        match right {
+            Object::Int(value) => Object::Int(!value),
         }
     }
 
-    fn eval_minus_prefix(&mut self, right: Object) -> Object {
+    fn eval_not_prefix(&mut self, right: Object) -> Object {
         match right {
-            Object::Int(value) => Object::Int(-value),
+            Object::Int(value) => Object::Int((value == 0) as i64),
         }
     }
 
@@ -72,15 +100,18 @@ impl Evaluator {
         left: i64,
         right: i64,
     ) -> Result<Object, RuntimeError> {
-        match infix {
-            Infix::Plus => Ok(Object::Int(left + right)),
-            Infix::Minus => Ok(Object::Int(left - right)),
-            Infix::Multiply => Ok(Object::Int(left * right)),
+        let result = match infix {
+            Infix::Plus => left.checked_add(right),
+            Infix::Minus => left.checked_sub(right),
+            Infix::Multiply => left.checked_mul(right),
             Infix::Divide => match right {
-                0 => Err(RuntimeError::DivideByZero),
-                _ => Ok(Object::Int(left / right)),
+                0 => return Err(RuntimeError::DivideByZero),
+                // i64::MIN / -1 is the only overflowing division.
+                _ => left.checked_div(right),
             },
-        }
+        };
+
+        result.map(Object::Int).ok_or(RuntimeError::Overflow)
     }
 
     fn eval_literal(&mut self, literal: Literal) -> Object {
@@ -123,11 +154,38 @@ mod tests {
     }
     #[test]
     fn test_prefix() {
-        let tests = vec![(
-            r#"-5
-        "#,
-            Ok(Object::Int(-5)),
-        )];
+        let tests = vec![
+            (
+                r#"-5
+            "#,
+                Ok(Object::Int(-5)),
+            ),
+            (
+                r#"+5
+            "#,
+                Ok(Object::Int(5)),
+            ),
+            (
+                r#"~5
+            "#,
+                Ok(Object::Int(-6)),
+            ),
+            (
+                r#"!5
+            "#,
+                Ok(Object::Int(0)),
+            ),
+            (
+                r#"!0
+            "#,
+                Ok(Object::Int(1)),
+            ),
+            (
+                r#"-+~!0
+            "#,
+                Ok(Object::Int(2)),
+            ),
+        ];
 
         for (input, expect) in tests {
             assert_eq!(expect, eval(input));
@@ -212,4 +270,21 @@ mod tests {
             assert_eq!(expect, eval(input));
         }
     }
+
+    #[test]
+    fn test_overflow() {
+        let tests = vec![
+            ("9223372036854775807+1", Err(RuntimeError::Overflow)),
+            ("0-9223372036854775807-2", Err(RuntimeError::Overflow)),
+            ("4611686018427387904*2", Err(RuntimeError::Overflow)),
+            ("-(0-9223372036854775807-1)", Err(RuntimeError::Overflow)),
+            ("(0-9223372036854775807-1)/-1", Err(RuntimeError::Overflow)),
+            ("0-9223372036854775807-1", Ok(Object::Int(i64::MIN))),
+            ("(0-9223372036854775807-1)/1", Ok(Object::Int(i64::MIN))),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, eval(input));
+        }
+    }
 }
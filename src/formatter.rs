@@ -0,0 +1,121 @@
+use crate::ast::*;
+
+/// Renders a program back to source, one statement per line, using the
+/// fewest parentheses that still parse back to the same tree.
+pub fn format(program: &Program) -> String {
+    let mut source = String::new();
+
+    for (n, s) in program.iter().enumerate() {
+        let Statement::Expression(ex) = s;
+
+        // A statement starting with `+` or `-` would be read as an infix
+        // operation on the previous line, so it has to be grouped.
+        if n > 0 && starts_with_sign(ex) {
+            source.push_str(&format!("({})\n", ex));
+        } else {
+            source.push_str(&format!("{}\n", ex));
+        }
+    }
+
+    source
+}
+
+fn starts_with_sign(ex: &Expr) -> bool {
+    match ex {
+        Expr::Literal(Literal::Int(i)) => *i < 0,
+        Expr::Prefix(p, _) => *p == Prefix::Plus || *p == Prefix::Minus,
+        Expr::Infix(_, x, _) => starts_with_sign(x),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::*;
+    use crate::differential::Generator;
+    use crate::formatter::format;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Program {
+        Parser::new(Lexer::new(input)).parse().unwrap()
+    }
+
+    #[test]
+    fn test_format() {
+        let tests = vec![
+            ("1", "1\n"),
+            ("1+2", "1 + 2\n"),
+            ("(1+2)", "1 + 2\n"),
+            ("1+(2+3)", "1 + (2 + 3)\n"),
+            ("(1+2)+3", "1 + 2 + 3\n"),
+            ("1-(2-3)", "1 - (2 - 3)\n"),
+            ("(1*2)+(3*4)", "1 * 2 + 3 * 4\n"),
+            ("(1+2)*(3+4)", "(1 + 2) * (3 + 4)\n"),
+            ("12/(3*4)", "12 / (3 * 4)\n"),
+            ("-(1)", "-(1)\n"),
+            ("-1", "-1\n"),
+            ("-(-1)", "--1\n"),
+            ("-(1+2)", "-(1 + 2)\n"),
+            ("--1", "--1\n"),
+            ("!~+(-1)*2", "!~+-1 * 2\n"),
+            ("1 - -2", "1 - -2\n"),
+            ("1 2*3", "1\n2 * 3\n"),
+            ("1 (-2)+3", "1\n(-2 + 3)\n"),
+            ("", ""),
+        ];
+
+        for (input, want) in tests {
+            assert_eq!(want, format(&parse(input)));
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let tests = vec![
+            "1",
+            "-30*2-(-60)",
+            "((1))+((2*3)-4)/5",
+            "1-(2-(3-(4-5)))",
+            "~(1+2)*!3/-(4/5)",
+            "+1 (-1) (+2)*3 !4",
+            "-(1) 1 -(1) -1 --(1)",
+            "(3+5)/2 5*(9-6) 2*3+4*5",
+        ];
+
+        for input in tests {
+            let program = parse(input);
+            assert_eq!(program, parse(&format(&program)));
+        }
+    }
+
+    #[test]
+    fn test_format_built_tree() {
+        let program = vec![Statement::Expression(Expr::Infix(
+            Infix::Multiply,
+            Box::new(Expr::Literal(Literal::Int(-2))),
+            Box::new(Expr::Infix(
+                Infix::Divide,
+                Box::new(Expr::Literal(Literal::Int(6))),
+                Box::new(Expr::Literal(Literal::Int(3))),
+            )),
+        ))];
+
+        assert_eq!("-2 * (6 / 3)\n", format(&program));
+    }
+
+    #[test]
+    fn test_round_trip_built_tree() {
+        let mut generator = Generator::new(27);
+        for _ in 0..1000 {
+            let program = vec![
+                Statement::Expression(generator.expr(4)),
+                Statement::Expression(generator.expr(4)),
+                Statement::Expression(Expr::Prefix(
+                    Prefix::Minus,
+                    Box::new(Expr::Literal(Literal::Int(i64::MIN))),
+                )),
+            ];
+            assert_eq!(program, parse(&format(&program)), "{}", format(&program));
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use crate::ast::*;
+use std::fmt;
+
+/// A virtual register. Every value is defined by exactly one instruction.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash)]
+pub struct Value(pub usize);
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    LogicalNot,
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UnaryOp::Neg => write!(f, "neg"),
+            UnaryOp::Not => write!(f, "not"),
+            UnaryOp::LogicalNot => write!(f, "lnot"),
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BinaryOp::Add => write!(f, "add"),
+            BinaryOp::Sub => write!(f, "sub"),
+            BinaryOp::Mul => write!(f, "mul"),
+            BinaryOp::Div => write!(f, "div"),
+        }
+    }
+}
+
+/// Three-address code: each instruction defines its first value.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Instruction {
+    Const(Value, i64),
+    Unary(Value, UnaryOp, Value),
+    Binary(Value, BinaryOp, Value, Value),
+}
+
+impl Instruction {
+    pub fn defines(&self) -> Value {
+        match *self {
+            Instruction::Const(d, _) => d,
+            Instruction::Unary(d, _, _) => d,
+            Instruction::Binary(d, _, _, _) => d,
+        }
+    }
+
+    pub fn uses(&self) -> Vec<Value> {
+        match *self {
+            Instruction::Const(_, _) => vec![],
+            Instruction::Unary(_, _, a) => vec![a],
+            Instruction::Binary(_, _, a, b) => vec![a, b],
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::Const(d, i) => write!(f, "{} = const {}", d, i),
+            Instruction::Unary(d, op, a) => write!(f, "{} = {} {}", d, op, a),
+            Instruction::Binary(d, op, a, b) => write!(f, "{} = {} {}, {}", d, op, a, b),
+        }
+    }
+}
+
+/// A straight-line function returning a single value.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Function {
+    pub name: String,
+    pub instructions: Vec<Instruction>,
+    pub result: Value,
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}:", self.name)?;
+        for i in &self.instructions {
+            writeln!(f, "  {}", i)?;
+        }
+        writeln!(f, "  ret {}", self.result)
+    }
+}
+
+/// Lowers a statement into a `main` function.
+///
+/// The operand that needs more values alive at once is lowered first
+/// (Sethi-Ullman order), which keeps register pressure low for backends.
+/// Both operands are always computed, so the order is only observable when
+/// both can raise an error: then the left one goes first, like in the
+/// evaluator.
+pub fn lower(s: &Statement) -> Function {
+    let mut lowering = Lowering {
+        instructions: vec![],
+    };
+
+    let Statement::Expression(ex) = s;
+    let result = lowering.lower_expression(ex);
+
+    Function {
+        name: "main".to_string(),
+        instructions: lowering.instructions,
+        result,
+    }
+}
+
+struct Lowering {
+    instructions: Vec<Instruction>,
+}
+
+impl Lowering {
+    fn next_value(&self) -> Value {
+        Value(self.instructions.len())
+    }
+
+    fn lower_expression(&mut self, ex: &Expr) -> Value {
+        match ex {
+            Expr::Literal(Literal::Int(i)) => {
+                let d = self.next_value();
+                self.instructions.push(Instruction::Const(d, *i));
+                d
+            }
+            Expr::Prefix(p, x) => {
+                let a = self.lower_expression(x);
+                let op = match *p {
+                    Prefix::Plus => return a,
+                    Prefix::Minus => UnaryOp::Neg,
+                    Prefix::BitNot => UnaryOp::Not,
+                    Prefix::Not => UnaryOp::LogicalNot,
+                };
+                let d = self.next_value();
+                self.instructions.push(Instruction::Unary(d, op, a));
+                d
+            }
+            Expr::Infix(i, x, y) => {
+                let left_first = need(x) >= need(y) || !(x.is_pure() || y.is_pure());
+                let (a, b) = match left_first {
+                    true => {
+                        let a = self.lower_expression(x);
+                        (a, self.lower_expression(y))
+                    }
+                    false => {
+                        let b = self.lower_expression(y);
+                        (self.lower_expression(x), b)
+                    }
+                };
+                let op = match *i {
+                    Infix::Plus => BinaryOp::Add,
+                    Infix::Minus => BinaryOp::Sub,
+                    Infix::Multiply => BinaryOp::Mul,
+                    Infix::Divide => BinaryOp::Div,
+                };
+                let d = self.next_value();
+                self.instructions.push(Instruction::Binary(d, op, a, b));
+                d
+            }
+        }
+    }
+}
+
+/// Sethi-Ullman number: how many values `ex` keeps alive at once.
+fn need(ex: &Expr) -> usize {
+    match ex {
+        Expr::Literal(_) => 1,
+        Expr::Prefix(_, x) => need(x),
+        Expr::Infix(_, x, y) => {
+            let (left, right) = (need(x), need(y));
+            match left == right {
+                true => left + 1,
+                false => left.max(right),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::Statement;
+    use crate::ir::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Statement {
+        Parser::new(Lexer::new(input)).parse().unwrap().remove(0)
+    }
+
+    #[test]
+    fn test_lower() {
+        let tests = vec![
+            ("1", vec![Instruction::Const(Value(0), 1)], Value(0)),
+            (
+                "+-(1)",
+                vec![
+                    Instruction::Const(Value(0), 1),
+                    Instruction::Unary(Value(1), UnaryOp::Neg, Value(0)),
+                ],
+                Value(1),
+            ),
+            (
+                "1-2",
+                vec![
+                    Instruction::Const(Value(0), 1),
+                    Instruction::Const(Value(1), 2),
+                    Instruction::Binary(Value(2), BinaryOp::Sub, Value(0), Value(1)),
+                ],
+                Value(2),
+            ),
+            (
+                "1/(2*3)",
+                vec![
+                    Instruction::Const(Value(0), 2),
+                    Instruction::Const(Value(1), 3),
+                    Instruction::Binary(Value(2), BinaryOp::Mul, Value(0), Value(1)),
+                    Instruction::Const(Value(3), 1),
+                    Instruction::Binary(Value(4), BinaryOp::Div, Value(3), Value(2)),
+                ],
+                Value(4),
+            ),
+            (
+                // Both operands can overflow, so the left one stays first.
+                "-(1)/(2*3)",
+                vec![
+                    Instruction::Const(Value(0), 1),
+                    Instruction::Unary(Value(1), UnaryOp::Neg, Value(0)),
+                    Instruction::Const(Value(2), 2),
+                    Instruction::Const(Value(3), 3),
+                    Instruction::Binary(Value(4), BinaryOp::Mul, Value(2), Value(3)),
+                    Instruction::Binary(Value(5), BinaryOp::Div, Value(1), Value(4)),
+                ],
+                Value(5),
+            ),
+        ];
+
+        for (input, instructions, result) in tests {
+            let f = lower(&parse(input));
+            assert_eq!(instructions, f.instructions);
+            assert_eq!(result, f.result);
+        }
+    }
+
+    #[test]
+    fn test_dump() {
+        let want = r#"main:
+  %0 = const 3
+  %1 = const 2
+  %2 = sub %0, %1
+  %3 = neg %2
+  %4 = const 8
+  %5 = lnot %4
+  %6 = not %5
+  %7 = div %6, %3
+  ret %7
+"#;
+
+        assert_eq!(want, lower(&parse("~!8/-(3-2)")).to_string());
+    }
+}
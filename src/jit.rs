@@ -0,0 +1,195 @@
+use crate::compiler::encoder::encode;
+use crate::compiler::{Compiler, Target, RETURNED_DIVIDE_BY_ZERO};
+use crate::error::RuntimeError;
+use crate::ir::Function;
+use std::io;
+
+/// What code generated with `return_errors` returns in rax and rdx.
+#[repr(C)]
+struct Output {
+    value: i64,
+    error: i64,
+}
+
+/// Machine code for a function, mapped into executable memory.
+#[derive(Debug)]
+pub struct JitFunction {
+    code: *mut u8,
+    len: usize,
+}
+
+impl JitFunction {
+    /// Compiles `f` with `compiler`, which always targets x86-64, returns the
+    /// result and any runtime error instead of printing them, and checks
+    /// divisions and overflow here, so results match the evaluator.
+    pub fn new(compiler: Compiler, f: &Function) -> io::Result<Self> {
+        let assembly = compiler
+            .target(Target::X86_64)
+            .print_result(false)
+            .check_division(true)
+            .check_overflow(true)
+            .define_traps(false)
+            .return_errors(true)
+            .generate(f);
+
+        Self::map(&encode(&assembly.instructions))
+    }
+
+    fn map(bytes: &[u8]) -> io::Result<Self> {
+        let len = bytes.len();
+        unsafe {
+            let code = libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            if code == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+
+            // Owned from here, so unmapped on error.
+            let f = JitFunction {
+                code: code as *mut u8,
+                len,
+            };
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), f.code, len);
+            if libc::mprotect(code, len, libc::PROT_READ | libc::PROT_EXEC) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(f)
+        }
+    }
+
+    pub fn call(&self) -> Result<i64, RuntimeError> {
+        let output = unsafe {
+            let f: extern "C" fn() -> Output = std::mem::transmute(self.code);
+            f()
+        };
+
+        match output.error {
+            0 => Ok(output.value),
+            RETURNED_DIVIDE_BY_ZERO => Err(RuntimeError::DivideByZero),
+            _ => Err(RuntimeError::Overflow),
+        }
+    }
+}
+
+impl Drop for JitFunction {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.code as *mut libc::c_void, self.len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::{Allocation, Compiler, Target};
+    use crate::error::RuntimeError;
+    use crate::evaluator::Evaluator;
+    use crate::ir::lower;
+    use crate::jit::JitFunction;
+    use crate::lexer::Lexer;
+    use crate::object::Object;
+    use crate::parser::Parser;
+
+    fn call(compiler: Compiler, input: &str) -> Result<i64, RuntimeError> {
+        let program = Parser::new(Lexer::new(input)).parse().unwrap();
+        JitFunction::new(compiler, &lower(&program[0]))
+            .unwrap()
+            .call()
+    }
+
+    #[test]
+    fn test_call() {
+        let tests = vec![
+            ("5", Ok(5)),
+            ("1 + 2 * 3", Ok(7)),
+            ("8 / -(3 - 2)", Ok(-8)),
+            ("~5 + 256", Ok(250)),
+            ("!0 - !7", Ok(1)),
+            ("9223372036854775807 + 1", Err(RuntimeError::Overflow)),
+            (
+                "-(0 - 9223372036854775807 - 1)",
+                Err(RuntimeError::Overflow),
+            ),
+            (
+                "(0 - 9223372036854775807 - 1) / -1",
+                Err(RuntimeError::Overflow),
+            ),
+            ("(0 - 9223372036854775807 - 1) / 1", Ok(i64::MIN)),
+            ("1 / (2 - 2)", Err(RuntimeError::DivideByZero)),
+        ];
+
+        for (input, want) in tests {
+            assert_eq!(want, call(Compiler::new(), input));
+        }
+    }
+
+    #[test]
+    fn test_call_matches_evaluator() {
+        let tests = vec![
+            "10 + 1",
+            "2 * 3 + 4 * 5",
+            "-30 * 2 - (-60)",
+            "((1 + 2) * (3 + 4)) / (5 - 6)",
+            "!(3 - 1) * ~!0",
+            "100 / 7 / 2",
+            "-7 / 2",
+            "5 / (1 - 1) + 1",
+            "1-(2-(3-(4-(5-(6-(7-(8-(9-(10-(11-(12-13)))))))))))",
+            "(1+2)*(3+4)*(5+6)*(7+8)*(9+10)/((11-12)*(13-14)*(15-16)*(17-18))",
+            "4611686018427387904 * 2",
+            "4611686018427387904 * -2",
+            "0 - 9223372036854775807 - 2",
+            "(0 - 9223372036854775807 - 1) / -1 / 0",
+            "(0 - 9223372036854775807 - 1) / (2 - 3)",
+        ];
+
+        for input in tests {
+            let program = Parser::new(Lexer::new(input)).parse().unwrap();
+            let want = Evaluator::new().eval(program);
+
+            for allocation in &[Allocation::Stack, Allocation::Registers] {
+                for peephole in &[false, true] {
+                    let compiler = Compiler::new().allocation(*allocation).peephole(*peephole);
+                    assert_eq!(want, call(compiler, input).map(Object::Int));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_call_repeatedly() {
+        let program = Parser::new(Lexer::new("6 * 7")).parse().unwrap();
+        let f = JitFunction::new(Compiler::new(), &lower(&program[0])).unwrap();
+
+        for _ in 0..3 {
+            assert_eq!(Ok(42), f.call());
+        }
+    }
+
+    #[test]
+    fn test_call_ignores_output_settings() {
+        let tests = vec![
+            ("6 * 7", Ok(42)),
+            (
+                "(0 - 9223372036854775807 - 1) / -1",
+                Err(RuntimeError::Overflow),
+            ),
+        ];
+
+        for (input, want) in tests {
+            let compiler = Compiler::new()
+                .target(Target::Aarch64)
+                .print_result(true)
+                .check_division(false)
+                .check_overflow(false)
+                .define_traps(true);
+            assert_eq!(want, call(compiler, input));
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::token::Token;
+use crate::token::{Span, Token};
 
 pub struct Lexer<'a> {
     input: &'a str,
@@ -18,12 +18,28 @@ impl<'a> Lexer<'a> {
 
         lexer.read_char();
 
-        return lexer;
+        lexer
     }
 
     pub fn next_token(&mut self) -> Token {
+        self.next_spanned_token().0
+    }
+
+    /// The next token and where it is. `Eof` is an empty span at the end.
+    pub fn next_spanned_token(&mut self) -> (Token, Span) {
         self.skip_whitespaces();
 
+        let start = self.pos;
+        let token = self.read_token();
+        let end = match token {
+            Token::Eof => start,
+            _ => self.pos,
+        };
+
+        (token, Span { start, end })
+    }
+
+    fn read_token(&mut self) -> Token {
         let token = match self.ch {
             b'0'..=b'9' => return self.consume_number(),
 
@@ -31,6 +47,8 @@ impl<'a> Lexer<'a> {
             b'-' => Token::Minus,
             b'*' => Token::Asterisk,
             b'/' => Token::Slash,
+            b'!' => Token::Bang,
+            b'~' => Token::Tilde,
 
             b'(' => Token::LeftParen,
             b')' => Token::RightParen,
@@ -41,7 +59,7 @@ impl<'a> Lexer<'a> {
 
         self.read_char();
 
-        return token;
+        token
     }
 
     fn consume_number(&mut self) -> Token {
@@ -53,12 +71,15 @@ impl<'a> Lexer<'a> {
 
         let consumed = &self.input[start_pos..self.pos];
 
-        return Token::Integer(consumed.parse::<i64>().unwrap());
+        // Only digits were consumed, so parsing can only fail by overflowing,
+        // and u64::MAX is out of range for the parser either way.
+        Token::Integer(consumed.parse::<u64>().unwrap_or(u64::MAX))
     }
 
     fn read_char(&mut self) {
         if self.next_pos >= self.input.len() {
             self.ch = 0;
+            self.pos = self.input.len();
             return;
         }
 
@@ -68,19 +89,30 @@ impl<'a> Lexer<'a> {
     }
 
     fn skip_whitespaces(&mut self) {
-        loop {
-            match self.ch {
-                b' ' | b'\t' | b'\n' | b'\r' => self.read_char(),
-                _ => break,
-            }
+        while let b' ' | b'\t' | b'\n' | b'\r' = self.ch {
+            self.read_char();
+        }
+    }
+}
+/// Lists the tokens of `input` one per line, each after its span.
+pub fn dump(input: &str) -> String {
+    let mut lexer = Lexer::new(input);
+    let mut dump = String::new();
+
+    loop {
+        let (token, span) = lexer.next_spanned_token();
+        dump.push_str(&format!("{:<8} {:?}\n", span.to_string(), token));
+        if token == Token::Eof {
+            return dump;
         }
     }
 }
+
 #[cfg(test)]
 mod tests {
 
-    use crate::lexer::Lexer;
-    use crate::token::Token;
+    use crate::lexer::{dump, Lexer};
+    use crate::token::{Span, Token};
 
     #[test]
     fn test_next_token() {
@@ -91,6 +123,7 @@ mod tests {
         7 / 8
         ()
         9*10
+        !~
 
         &
         "#;
@@ -112,6 +145,8 @@ mod tests {
             Token::Integer(9),
             Token::Asterisk,
             Token::Integer(10),
+            Token::Bang,
+            Token::Tilde,
             Token::Illegal,
             Token::Eof,
         ];
@@ -123,4 +158,61 @@ mod tests {
             assert_eq!(want, got);
         }
     }
+
+    #[test]
+    fn test_number_at_end_of_input() {
+        let mut lexer = Lexer::new("1+23");
+
+        let tests = vec![
+            Token::Integer(1),
+            Token::Plus,
+            Token::Integer(23),
+            Token::Eof,
+        ];
+
+        for want in tests {
+            assert_eq!(want, lexer.next_token());
+        }
+    }
+
+    #[test]
+    fn test_spans() {
+        let tests = vec![
+            ("1", vec![(Token::Integer(1), 0, 1), (Token::Eof, 1, 1)]),
+            (
+                " 12 +(3)",
+                vec![
+                    (Token::Integer(12), 1, 3),
+                    (Token::Plus, 4, 5),
+                    (Token::LeftParen, 5, 6),
+                    (Token::Integer(3), 6, 7),
+                    (Token::RightParen, 7, 8),
+                    (Token::Eof, 8, 8),
+                ],
+            ),
+            (
+                "\n\t1\0",
+                vec![(Token::Integer(1), 2, 3), (Token::Eof, 3, 3)],
+            ),
+        ];
+
+        for (input, want) in tests {
+            let mut lexer = Lexer::new(input);
+            for (token, start, end) in want {
+                assert_eq!((token, Span { start, end }), lexer.next_spanned_token());
+            }
+        }
+    }
+
+    #[test]
+    fn test_dump() {
+        let want = r#"0..2     Integer(10)
+3..4     Slash
+4..5     Minus
+5..6     Integer(2)
+6..6     Eof
+"#;
+
+        assert_eq!(want, dump("10 /-2"));
+    }
 }
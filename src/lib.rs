@@ -1,8 +1,17 @@
 pub mod ast;
+pub mod bytecode;
 pub mod compiler;
+#[cfg(test)]
+mod differential;
 pub mod error;
 pub mod evaluator;
+pub mod formatter;
+pub mod ir;
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+pub mod jit;
 pub mod lexer;
 pub mod object;
+pub mod optimizer;
 pub mod parser;
 pub mod token;
+pub mod vm;
@@ -2,12 +2,15 @@ extern crate calculator;
 
 #[macro_use(crate_version, crate_authors)]
 extern crate clap;
-use clap::{App, Arg};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::io::Write;
 
 enum ExitCode {
     Ok,
     InputError,
     ParseError,
+    OutputError,
+    RuntimeError,
 }
 
 fn try_read_from_stdin<T: std::str::FromStr>() -> Result<T, T::Err> {
@@ -17,10 +20,7 @@ fn try_read_from_stdin<T: std::str::FromStr>() -> Result<T, T::Err> {
 }
 
 fn try_read_from_file(path: String) -> Option<String> {
-    match std::fs::read_to_string(path) {
-        Ok(f) => Some(f),
-        Err(_) => None,
-    }
+    std::fs::read_to_string(path).ok()
 }
 
 fn main() {
@@ -33,47 +33,545 @@ fn main() {
                 .short("f")
                 .long("file")
                 .help("File path to compile")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("registers")
+                .long("registers")
+                .help("Keeps intermediate values in registers instead of the stack"),
+        )
+        .arg(
+            Arg::with_name("emit")
+                .long("emit")
+                .help("Outputs to emit; several at once are written to files named after -o or -f")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .use_delimiter(true)
+                .possible_values(&[
+                    "tokens", "ast", "asm", "ir", "wat", "wasm", "c", "llvm", "exe", "obj",
+                ])
+                .default_value("asm")
+                .requires_ifs(&[("exe", "o"), ("obj", "o")]),
+        )
+        .arg(
+            Arg::with_name("o")
+                .short("o")
+                .long("output")
+                .help("File path to write the executable or object file to")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("symbol")
+                .long("symbol")
+                .help("Name of the function to define in object files or with --exit-code [default: main]")
+                .takes_value(true)
+                .required_ifs(&[("emit", "obj")])
+                .validator(validate_symbol),
+        )
+        .arg(
+            Arg::with_name("header")
+                .long("header")
+                .help("File path to write a C header declaring the function of --emit c or obj to")
+                .takes_value(true)
+                .requires("symbol"),
+        )
+        .arg(
+            Arg::with_name("no-main")
+                .long("no-main")
+                .help("Leaves out main when emitting C"),
+        )
+        .arg(
+            Arg::with_name("exit-code")
+                .long("exit-code")
+                .help("Makes programs return the result as the exit status instead of printing it"),
+        )
+        .arg(Arg::with_name("checked").long("checked").help(
+            "Stops programs on integer overflow instead of wrapping around; like a \
+                     division by 0, it is reported on stderr with exit status 1",
+        ))
+        .arg(
+            Arg::with_name("syntax")
+                .long("syntax")
+                .help("Assembler syntax to emit")
+                .takes_value(true)
+                .possible_values(&["intel", "att"])
+                .default_value("intel"),
+        )
+        .arg(
+            Arg::with_name("target")
+                .long("target")
+                .help("Platform to generate code for")
+                .takes_value(true)
+                .possible_values(&["x86_64-linux", "aarch64-linux", "riscv64"])
+                .default_value("x86_64-linux"),
+        )
+        .arg(
+            Arg::with_name("jit")
+                .long("jit")
+                .help("Runs the program as machine code in memory and prints the result")
+                .conflicts_with_all(&["emit", "target"]),
+        )
+        .arg(
+            Arg::with_name("O")
+                .short("O")
+                .help("Optimizes the program before compiling")
+                .global(true),
+        )
+        .subcommand(
+            SubCommand::with_name("fmt")
+                .about("Rewrites files in canonical format")
+                .arg(
+                    Arg::with_name("files")
+                        .help("File paths to format")
+                        .required(true)
+                        .multiple(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("compile")
+                .about("Compiles a program to a file")
+                .arg(
+                    Arg::with_name("emit")
+                        .long("emit")
+                        .help("Output to emit")
+                        .takes_value(true)
+                        .possible_values(&["bytecode"])
+                        .default_value("bytecode"),
+                )
+                .arg(
+                    Arg::with_name("o")
+                        .short("o")
+                        .long("output")
+                        .help("File path to write")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("run")
+                .about("Runs a bytecode file and prints the result")
+                .arg(
+                    Arg::with_name("file")
+                        .help("Bytecode file path")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("disassemble")
+                .about("Prints the contents of a bytecode file")
+                .arg(
+                    Arg::with_name("file")
+                        .help("Bytecode file path")
+                        .required(true),
+                ),
+        )
         .get_matches();
 
+    if let Some(arguments) = arguments.subcommand_matches("fmt") {
+        return exit(format_files(arguments));
+    }
+    if let Some(arguments) = arguments.subcommand_matches("compile") {
+        return exit(compile_file(arguments));
+    }
+    if let Some(arguments) = arguments.subcommand_matches("run") {
+        return exit(run_file(arguments));
+    }
+    if let Some(arguments) = arguments.subcommand_matches("disassemble") {
+        return exit(disassemble_file(arguments));
+    }
+
+    let emits: Vec<&str> = arguments.values_of("emit").unwrap().collect();
+
+    let input = match read_input(&arguments) {
+        Ok(x) => x,
+        Err(code) => return exit(code),
+    };
+
+    // Tokens come straight from the lexer, so they can be listed even for
+    // input that does not parse.
+    let program = match emits.iter().all(|&emit| emit == "tokens") {
+        true => vec![],
+        false => match parse_program(&arguments, &input) {
+            Ok(x) => x,
+            Err(code) => return exit(code),
+        },
+    };
+
+    if let Some(path) = arguments.value_of("header") {
+        let symbol = symbol(&arguments);
+        let header = match (emits.contains(&"c"), emits.contains(&"obj")) {
+            (true, false) => calculator::compiler::c::header(symbol),
+            (false, true) => calculator::compiler::c::object_header(symbol),
+            _ => {
+                println!("--header needs exactly one of --emit c and --emit obj.");
+                return exit(ExitCode::InputError);
+            }
+        };
+        if std::fs::write(path, header).is_err() {
+            return exit(ExitCode::OutputError);
+        }
+    }
+
+    if arguments.is_present("jit") {
+        if let Some(s) = program.first() {
+            let mut function = calculator::ir::lower(s);
+            function.name = symbol(&arguments).to_string();
+            let compiler = calculator::compiler::Compiler::new()
+                .allocation(allocation(&arguments))
+                .peephole(arguments.is_present("O"));
+            return exit(run_jit(compiler, &function));
+        }
+        return exit(ExitCode::Ok);
+    }
+
+    if let [emit] = emits[..] {
+        let bytes = match render(emit, &arguments, &input, &program) {
+            Ok(Some(x)) => x,
+            Ok(None) => return exit(ExitCode::Ok),
+            Err(code) => return exit(code),
+        };
+        return exit(match emit {
+            "exe" | "obj" => write_output(emit, arguments.value_of("o").unwrap(), &bytes),
+            _ => match std::io::stdout().write_all(&bytes) {
+                Ok(_) => ExitCode::Ok,
+                Err(_) => ExitCode::OutputError,
+            },
+        });
+    }
+
+    let base = output_base(&arguments);
+    for emit in emits {
+        match render(emit, &arguments, &input, &program) {
+            Ok(Some(bytes)) => match write_output(emit, &output_path(&base, emit), &bytes) {
+                ExitCode::Ok => {}
+                code => return exit(code),
+            },
+            Ok(None) => {}
+            Err(code) => return exit(code),
+        }
+    }
+
+    exit(ExitCode::Ok)
+}
+
+fn allocation(arguments: &ArgMatches) -> calculator::compiler::Allocation {
+    match arguments.is_present("registers") {
+        true => calculator::compiler::Allocation::Registers,
+        false => calculator::compiler::Allocation::Stack,
+    }
+}
+
+/// Renders one `--emit` output. Only the first statement is compiled, so
+/// there is nothing to compile in an empty program and `None` is returned.
+fn render(
+    emit: &str,
+    arguments: &ArgMatches,
+    input: &str,
+    program: &calculator::ast::Program,
+) -> Result<Option<Vec<u8>>, ExitCode> {
+    let s = match emit {
+        "tokens" => return Ok(Some(calculator::lexer::dump(input).into_bytes())),
+        "ast" => return Ok(Some(calculator::ast::dump(program).into_bytes())),
+        _ => match program.first() {
+            Some(s) => s,
+            None => return Ok(None),
+        },
+    };
+
+    let syntax = match arguments.value_of("syntax") {
+        Some("att") => calculator::compiler::printer::Syntax::Att,
+        _ => calculator::compiler::printer::Syntax::Intel,
+    };
+
+    let target = match arguments.value_of("target") {
+        Some("aarch64-linux") => calculator::compiler::Target::Aarch64,
+        Some("riscv64") => calculator::compiler::Target::Riscv64,
+        _ => calculator::compiler::Target::X86_64,
+    };
+
+    let mut function = calculator::ir::lower(s);
+    function.name = symbol(arguments).to_string();
+
+    let text = match emit {
+        "ir" => function.to_string(),
+        "c" => calculator::compiler::c::print(&function, !arguments.is_present("no-main")),
+        "llvm" => calculator::compiler::llvm::print(&function, !arguments.is_present("exit-code")),
+        "wat" => calculator::compiler::wasm::lower(&function).to_string(),
+        "wasm" => return Ok(Some(calculator::compiler::wasm::lower(&function).encode())),
+        "exe" | "obj" => {
+            if target != calculator::compiler::Target::X86_64 {
+                println!("Machine code can only be written for x86_64-linux.");
+                return Err(ExitCode::OutputError);
+            }
+            // Object files define a function rather than a program, so
+            // they return runtime errors to the caller.
+            let assembly = calculator::compiler::Compiler::new()
+                .allocation(allocation(arguments))
+                .peephole(arguments.is_present("O"))
+                .check_division(true)
+                .check_overflow(arguments.is_present("checked"))
+                .define_traps(emit == "exe")
+                .return_errors(emit == "obj")
+                .print_result(emit == "exe" && !arguments.is_present("exit-code"))
+                .generate(&function);
+            return Ok(Some(match emit {
+                "exe" => calculator::compiler::elf::executable(&assembly),
+                _ => calculator::compiler::elf::object(&assembly),
+            }));
+        }
+        _ => calculator::compiler::Compiler::new()
+            .allocation(allocation(arguments))
+            .peephole(arguments.is_present("O"))
+            .syntax(syntax)
+            .target(target)
+            .check_division(true)
+            .check_overflow(arguments.is_present("checked"))
+            .define_traps(true)
+            .print_result(!arguments.is_present("exit-code"))
+            .compile_function(&function),
+    };
+
+    Ok(Some(text.into_bytes()))
+}
+
+/// Where several outputs go: next to `-o`, or else next to the `-f` file.
+fn output_base(arguments: &ArgMatches) -> String {
+    if let Some(o) = arguments.value_of("o") {
+        return o.to_string();
+    }
+    match arguments.value_of("f") {
+        Some(f) => std::path::Path::new(f)
+            .with_extension("")
+            .to_string_lossy()
+            .into_owned(),
+        None => "out".to_string(),
+    }
+}
+
+/// The file `emit` is written to when several outputs are emitted at once.
+fn output_path(base: &str, emit: &str) -> String {
+    match emit {
+        "exe" => base.to_string(),
+        "asm" => format!("{}.s", base),
+        "llvm" => format!("{}.ll", base),
+        "obj" => format!("{}.o", base),
+        _ => format!("{}.{}", base, emit),
+    }
+}
+
+fn write_output(emit: &str, path: &str, bytes: &[u8]) -> ExitCode {
+    match emit {
+        "exe" => write_executable(path, bytes),
+        _ => match std::fs::write(path, bytes) {
+            Ok(_) => ExitCode::Ok,
+            Err(_) => ExitCode::OutputError,
+        },
+    }
+}
+
+/// Accepts names C can declare.
+fn symbol<'a>(arguments: &'a ArgMatches) -> &'a str {
+    arguments.value_of("symbol").unwrap_or("main")
+}
+
+fn validate_symbol(symbol: String) -> Result<(), String> {
+    let mut chars = symbol.chars();
+    let valid = match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    };
+
+    match valid {
+        true => Ok(()),
+        false => Err(format!("{} is not a C identifier", symbol)),
+    }
+}
+
+/// Reads the source from the file given with `-f`, or from stdin.
+fn read_input(arguments: &ArgMatches) -> Result<String, ExitCode> {
     let mut input: String = if let Some(f) = arguments.value_of("f") {
         match try_read_from_file(f.to_string()) {
             Some(s) => s,
-            _ => return exit(ExitCode::InputError),
+            _ => return Err(ExitCode::InputError),
         }
     } else {
         match try_read_from_stdin::<String>() {
             Ok(x) => x,
-            _ => return exit(ExitCode::InputError),
+            _ => return Err(ExitCode::InputError),
         }
     };
 
     input += "\0";
+    Ok(input)
+}
 
-    let l = calculator::lexer::Lexer::new(&input);
+/// Parses the source and optimizes it if `-O` is set.
+fn parse_program(
+    arguments: &ArgMatches,
+    input: &str,
+) -> Result<calculator::ast::Program, ExitCode> {
+    let l = calculator::lexer::Lexer::new(input);
     let mut p = calculator::parser::Parser::new(l);
 
     let program = match p.parse() {
         Ok(x) => x,
         Err(e) => {
             println!("{:?}", e);
-            return exit(ExitCode::ParseError);
+            return Err(ExitCode::ParseError);
+        }
+    };
+
+    match arguments.is_present("O") {
+        true => Ok(calculator::optimizer::optimize(program)),
+        false => Ok(program),
+    }
+}
+
+fn compile_file(arguments: &ArgMatches) -> ExitCode {
+    let program = match read_input(arguments).and_then(|input| parse_program(arguments, &input)) {
+        Ok(x) => x,
+        Err(code) => return code,
+    };
+
+    let chunk = calculator::bytecode::compile(&program);
+    match std::fs::write(arguments.value_of("o").unwrap(), chunk.encode()) {
+        Ok(_) => ExitCode::Ok,
+        Err(_) => ExitCode::OutputError,
+    }
+}
+
+#[cfg(unix)]
+fn write_executable(path: &str, bytes: &[u8]) -> ExitCode {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o755)
+        .open(path);
+
+    match file.and_then(|mut f| f.write_all(bytes)) {
+        Ok(_) => ExitCode::Ok,
+        Err(_) => ExitCode::OutputError,
+    }
+}
+
+#[cfg(not(unix))]
+fn write_executable(path: &str, bytes: &[u8]) -> ExitCode {
+    match std::fs::write(path, bytes) {
+        Ok(_) => ExitCode::Ok,
+        Err(_) => ExitCode::OutputError,
+    }
+}
+
+fn read_chunk(arguments: &ArgMatches) -> Result<calculator::bytecode::Chunk, ExitCode> {
+    let bytes = match std::fs::read(arguments.value_of("file").unwrap()) {
+        Ok(x) => x,
+        Err(_) => return Err(ExitCode::InputError),
+    };
+
+    calculator::bytecode::Chunk::decode(&bytes).map_err(|e| {
+        println!("{}", e);
+        ExitCode::InputError
+    })
+}
+
+fn run_file(arguments: &ArgMatches) -> ExitCode {
+    let chunk = match read_chunk(arguments) {
+        Ok(x) => x,
+        Err(code) => return code,
+    };
+
+    match calculator::vm::Vm::new().run(&chunk) {
+        Ok(result) => {
+            println!("{}", result);
+            ExitCode::Ok
+        }
+        Err(e) => {
+            println!("{}", e);
+            ExitCode::RuntimeError
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn run_jit(
+    compiler: calculator::compiler::Compiler,
+    function: &calculator::ir::Function,
+) -> ExitCode {
+    let f = match calculator::jit::JitFunction::new(compiler, function) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("{}", e);
+            return ExitCode::OutputError;
         }
     };
 
-    for s in program {
-        let code = calculator::compiler::Compiler::new().compile(s);
-        print!("{}", code);
+    match f.call() {
+        Ok(result) => {
+            println!("{}", result);
+            ExitCode::Ok
+        }
+        Err(e) => {
+            println!("{}", e);
+            ExitCode::RuntimeError
+        }
+    }
+}
 
-        exit(ExitCode::Ok)
+#[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+fn run_jit(_: calculator::compiler::Compiler, _: &calculator::ir::Function) -> ExitCode {
+    println!("The JIT only runs on x86-64 Linux.");
+    ExitCode::OutputError
+}
+
+fn disassemble_file(arguments: &ArgMatches) -> ExitCode {
+    match read_chunk(arguments) {
+        Ok(chunk) => {
+            print!("{}", chunk.disassemble());
+            ExitCode::Ok
+        }
+        Err(code) => code,
     }
 }
 
+fn format_files(arguments: &ArgMatches) -> ExitCode {
+    for f in arguments.values_of("files").unwrap() {
+        let input = match try_read_from_file(f.to_string()) {
+            Some(s) => s,
+            _ => return ExitCode::InputError,
+        };
+
+        let l = calculator::lexer::Lexer::new(&input);
+        let mut p = calculator::parser::Parser::new(l);
+
+        let program = match p.parse() {
+            Ok(x) => x,
+            Err(e) => {
+                println!("{}: {:?}", f, e);
+                return ExitCode::ParseError;
+            }
+        };
+
+        if std::fs::write(f, calculator::formatter::format(&program)).is_err() {
+            return ExitCode::OutputError;
+        }
+    }
+
+    ExitCode::Ok
+}
+
 fn exit(code: ExitCode) {
     match code {
         ExitCode::Ok => std::process::exit(0),
         ExitCode::InputError => std::process::exit(-1),
         ExitCode::ParseError => std::process::exit(-2),
+        ExitCode::OutputError => std::process::exit(-3),
+        ExitCode::RuntimeError => std::process::exit(-4),
     }
 }
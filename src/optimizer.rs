@@ -0,0 +1,173 @@
+use crate::ast::*;
+
+pub fn optimize(program: Program) -> Program {
+    Optimizer::new().fold_program(program)
+}
+
+/// Folds constant subtrees and removes algebraic identities.
+///
+/// Anything that would fail at runtime, a division by zero or an overflow,
+/// is left in the tree so the program still fails the same way.
+#[derive(Debug, Default)]
+pub struct Optimizer {}
+
+impl Optimizer {
+    pub fn new() -> Self {
+        Optimizer {}
+    }
+
+    fn optimize_prefix(&self, prefix: Prefix, x: Expr) -> Expr {
+        match (prefix, x) {
+            (Prefix::Plus, x) => x,
+            (Prefix::Minus, Expr::Literal(Literal::Int(i))) if i != i64::MIN => {
+                Expr::Literal(Literal::Int(-i))
+            }
+            (Prefix::BitNot, Expr::Literal(Literal::Int(i))) => Expr::Literal(Literal::Int(!i)),
+            (Prefix::Not, Expr::Literal(Literal::Int(i))) => {
+                Expr::Literal(Literal::Int((i == 0) as i64))
+            }
+            (Prefix::BitNot, Expr::Prefix(Prefix::BitNot, x)) => *x,
+            (prefix, x) => Expr::Prefix(prefix, Box::new(x)),
+        }
+    }
+
+    fn optimize_infix(&self, infix: Infix, x: Expr, y: Expr) -> Expr {
+        if let (Expr::Literal(Literal::Int(l)), Expr::Literal(Literal::Int(r))) = (&x, &y) {
+            let folded = match infix {
+                Infix::Plus => l.checked_add(*r),
+                Infix::Minus => l.checked_sub(*r),
+                Infix::Multiply => l.checked_mul(*r),
+                Infix::Divide => l.checked_div(*r),
+            };
+            if let Some(i) = folded {
+                return Expr::Literal(Literal::Int(i));
+            }
+        }
+
+        match (infix, x, y) {
+            (Infix::Plus, Expr::Literal(Literal::Int(0)), y) => y,
+            (Infix::Plus, x, Expr::Literal(Literal::Int(0))) => x,
+            (Infix::Minus, x, Expr::Literal(Literal::Int(0))) => x,
+            (Infix::Multiply, Expr::Literal(Literal::Int(1)), y) => y,
+            (Infix::Multiply, x, Expr::Literal(Literal::Int(1))) => x,
+            (Infix::Divide, x, Expr::Literal(Literal::Int(1))) => x,
+            (Infix::Multiply, Expr::Literal(Literal::Int(0)), y) if y.is_pure() => {
+                Expr::Literal(Literal::Int(0))
+            }
+            (Infix::Multiply, x, Expr::Literal(Literal::Int(0))) if x.is_pure() => {
+                Expr::Literal(Literal::Int(0))
+            }
+            (infix, x, y) => Expr::Infix(infix, Box::new(x), Box::new(y)),
+        }
+    }
+}
+
+impl Fold for Optimizer {
+    fn fold_expr(&mut self, ex: Expr) -> Expr {
+        match walk_fold_expr(self, ex) {
+            Expr::Prefix(p, x) => self.optimize_prefix(p, *x),
+            Expr::Infix(i, x, y) => self.optimize_infix(i, *x, *y),
+            ex => ex,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::*;
+    use crate::evaluator::Evaluator;
+    use crate::lexer::Lexer;
+    use crate::optimizer::optimize;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Program {
+        Parser::new(Lexer::new(input)).parse().unwrap()
+    }
+
+    fn int(i: i64) -> Expr {
+        Expr::Literal(Literal::Int(i))
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        let tests = vec![
+            ("1", int(1)),
+            ("1+2*3", int(7)),
+            ("(3+5)/2", int(4)),
+            ("-30*2-(-60)", int(0)),
+            ("-5", int(-5)),
+            ("+~!0", int(-2)),
+            ("100000*100000", int(10_000_000_000)),
+        ];
+
+        for (input, want) in tests {
+            assert_eq!(vec![Statement::Expression(want)], optimize(parse(input)));
+        }
+    }
+
+    #[test]
+    fn test_simplification() {
+        let tests = vec![
+            ("1/0", "1/0"),
+            ("1/(2-2)", "1/0"),
+            ("-(1/0)", "-(1/0)"),
+            // -x overflows for i64::MIN, so --x is not x.
+            ("--(1/0)", "--(1/0)"),
+            ("~~(1/0)", "1/0"),
+            ("+(1/0)", "1/0"),
+            ("(1/0)+0", "1/0"),
+            ("0+(1/0)", "1/0"),
+            ("(1/0)-(3-3)", "1/0"),
+            ("(1/0)*1", "1/0"),
+            ("(2-1)*(1/0)", "1/0"),
+            ("(1/0)/1", "1/0"),
+            ("(1/0)*0", "(1/0)*0"),
+            ("0*(1/0)", "0*(1/0)"),
+            ("(1/0)*(4-2)", "(1/0)*2"),
+        ];
+
+        for (input, want) in tests {
+            assert_eq!(parse(want), optimize(parse(input)));
+        }
+    }
+
+    #[test]
+    fn test_overflow_is_not_folded() {
+        let tests = vec![
+            "9223372036854775807+1",
+            "0-9223372036854775807-2",
+            "4611686018427387904*2",
+        ];
+
+        for input in tests {
+            match &optimize(parse(input))[0] {
+                Statement::Expression(Expr::Infix(_, _, _)) => {}
+                s => panic!("{} was folded into {:?}", input, s),
+            }
+        }
+    }
+
+    #[test]
+    fn test_preserves_evaluation() {
+        let tests = vec![
+            "2*3+4*5",
+            "5*(9-6)",
+            "-1+20",
+            "!(3-3)*~4",
+            "1+10/0",
+            "(1/0)*0",
+            "7/(1-1)+0",
+            "(9223372036854775807+1)*0",
+            "0*-(0-9223372036854775807-1)",
+            "--~9223372036854775807",
+        ];
+
+        for input in tests {
+            let program = parse(input);
+            assert_eq!(
+                Evaluator::new().eval(program.clone()),
+                Evaluator::new().eval(optimize(program))
+            );
+        }
+    }
+}
@@ -20,7 +20,7 @@ impl<'a> Parser<'a> {
         parser.advance_token();
         parser.advance_token();
 
-        return parser;
+        parser
     }
 
     pub fn parse(&mut self) -> Result<Program, ParseError> {
@@ -36,7 +36,7 @@ impl<'a> Parser<'a> {
             self.advance_token();
         }
 
-        return Ok(program);
+        Ok(program)
     }
 
     fn parse_statement(&mut self) -> Result<Statement, ParseError> {
@@ -56,7 +56,9 @@ impl<'a> Parser<'a> {
     fn parse_expression(&mut self, precedence: Precedence) -> Result<Expr, ParseError> {
         let mut left = match self.current_token {
             Token::Integer(_) => self.parse_int_expression(),
-            Token::Minus => self.parse_prefix_expression(),
+            Token::Plus | Token::Minus | Token::Bang | Token::Tilde => {
+                self.parse_prefix_expression()
+            }
             Token::LeftParen => self.parse_grouped_expression(),
             _ => {
                 return Err(ParseError::FoundUnexpectedToken);
@@ -72,12 +74,27 @@ impl<'a> Parser<'a> {
                 _ => return left,
             }
         }
-        return left;
+        left
     }
 
+    /// A minus sign right before an integer is part of the literal, so that
+    /// `-9223372036854775808` can be written and negative literals print back
+    /// to the same tree.
     fn parse_prefix_expression(&mut self) -> Result<Expr, ParseError> {
+        if let (Token::Minus, Token::Integer(int)) = (&self.current_token, &self.next_token) {
+            let int = *int;
+            self.advance_token();
+            return match int <= i64::MIN.unsigned_abs() {
+                true => Ok(Expr::Literal(Literal::Int((int as i64).wrapping_neg()))),
+                false => Err(ParseError::FoundOutOfRangeInteger),
+            };
+        }
+
         let prefix = match self.current_token {
+            Token::Plus => Prefix::Plus,
             Token::Minus => Prefix::Minus,
+            Token::Bang => Prefix::Not,
+            Token::Tilde => Prefix::BitNot,
             _ => unreachable!("parse_prefix_expression"),
         };
 
@@ -121,7 +138,10 @@ impl<'a> Parser<'a> {
 
     fn parse_int_expression(&mut self) -> Result<Expr, ParseError> {
         match self.current_token {
-            Token::Integer(ref mut int) => Ok(Expr::Literal(Literal::Int(int.clone()))),
+            Token::Integer(int) if int <= i64::MAX as u64 => {
+                Ok(Expr::Literal(Literal::Int(int as i64)))
+            }
+            Token::Integer(_) => Err(ParseError::FoundOutOfRangeInteger),
             _ => unreachable!("parse_int_expression"),
         }
     }
@@ -134,18 +154,18 @@ impl<'a> Parser<'a> {
     fn expect_next_token(&mut self, token: Token) -> bool {
         if self.next_token == token.clone() {
             self.advance_token();
-            return true;
+            true
         } else {
-            return false;
+            false
         }
     }
 
     fn current_precedence(&mut self) -> Precedence {
-        return Self::token_to_precedence(&self.current_token);
+        Self::token_to_precedence(&self.current_token)
     }
 
     fn next_precedence(&mut self) -> Precedence {
-        return Self::token_to_precedence(&self.next_token);
+        Self::token_to_precedence(&self.next_token)
     }
 
     fn token_to_precedence(tok: &Token) -> Precedence {
@@ -174,11 +194,45 @@ mod tests {
             (
                 r#"-2000
                 "#,
+                vec![Statement::Expression(Expr::Literal(Literal::Int(-2000)))],
+            ),
+            (
+                r#"-9223372036854775808
+                "#,
+                vec![Statement::Expression(Expr::Literal(Literal::Int(i64::MIN)))],
+            ),
+            (
+                r#"-(2000)
+                "#,
                 vec![Statement::Expression(Expr::Prefix(
                     Prefix::Minus,
                     Box::new(Expr::Literal(Literal::Int(2000))),
                 ))],
             ),
+            (
+                r#"+2000
+                "#,
+                vec![Statement::Expression(Expr::Prefix(
+                    Prefix::Plus,
+                    Box::new(Expr::Literal(Literal::Int(2000))),
+                ))],
+            ),
+            (
+                r#"~2000
+                "#,
+                vec![Statement::Expression(Expr::Prefix(
+                    Prefix::BitNot,
+                    Box::new(Expr::Literal(Literal::Int(2000))),
+                ))],
+            ),
+            (
+                r#"!-2000
+                "#,
+                vec![Statement::Expression(Expr::Prefix(
+                    Prefix::Not,
+                    Box::new(Expr::Literal(Literal::Int(-2000))),
+                ))],
+            ),
             (
                 r#"3000 + 4000
                 "#,
@@ -258,6 +312,26 @@ mod tests {
                 "#,
                 ParseError::FoundUnterminatedParentheses,
             ),
+            (
+                r#"9223372036854775808
+                "#,
+                ParseError::FoundOutOfRangeInteger,
+            ),
+            (
+                r#"-9223372036854775809
+                "#,
+                ParseError::FoundOutOfRangeInteger,
+            ),
+            (
+                r#"99999999999999999999
+                "#,
+                ParseError::FoundOutOfRangeInteger,
+            ),
+            (
+                r#"-99999999999999999999
+                "#,
+                ParseError::FoundOutOfRangeInteger,
+            ),
         ];
 
         for (input, want) in tests {
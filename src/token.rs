@@ -1,15 +1,33 @@
+use std::fmt;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     Illegal,
     Eof,
 
-    Integer(i64),
+    /// The digits of an integer, which may only fit once negated.
+    Integer(u64),
 
     Plus,
     Minus,
     Asterisk,
     Slash,
+    Bang,
+    Tilde,
 
     LeftParen,
     RightParen,
 }
+
+/// Where a token is in the input, as byte offsets with `end` excluded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
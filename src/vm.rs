@@ -0,0 +1,136 @@
+use crate::bytecode::{Chunk, Opcode};
+use crate::error::RuntimeError;
+use crate::object::Object;
+
+/// Runs compiled chunks. The stack is kept between runs, so evaluating the
+/// same chunk repeatedly does not allocate.
+#[derive(Debug, Default)]
+pub struct Vm {
+    stack: Vec<i64>,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Vm { stack: vec![] }
+    }
+
+    /// Runs `chunk`, which has to come from `bytecode::compile` or a
+    /// validated file.
+    ///
+    /// Like the evaluator, a division by zero and an overflow are errors.
+    pub fn run(&mut self, chunk: &Chunk) -> Result<Object, RuntimeError> {
+        self.stack.clear();
+        let mut pc = 0;
+
+        loop {
+            let op = Opcode::from_byte(chunk.code[pc]).unwrap();
+            match op {
+                Opcode::Push => self.stack.push(chunk.constants[chunk.read_index(pc)]),
+                Opcode::Neg => self.unary(i64::checked_neg)?,
+                Opcode::BitNot => self.unary(|x| Some(!x))?,
+                Opcode::Not => self.unary(|x| Some((x == 0) as i64))?,
+                Opcode::Add => self.binary(i64::checked_add)?,
+                Opcode::Sub => self.binary(i64::checked_sub)?,
+                Opcode::Mul => self.binary(i64::checked_mul)?,
+                Opcode::Div => {
+                    if self.stack[self.stack.len() - 1] == 0 {
+                        return Err(RuntimeError::DivideByZero);
+                    }
+                    self.binary(i64::checked_div)?
+                }
+                Opcode::Return => {
+                    return match self.stack.pop() {
+                        Some(x) => Ok(Object::Int(x)),
+                        None => Err(RuntimeError::FoundNoProgram),
+                    }
+                }
+            }
+            pc += 1 + op.operand_len();
+        }
+    }
+
+    /// Applies `f` to the top of the stack, which returns `None` when the
+    /// result overflows.
+    fn unary(&mut self, f: impl Fn(i64) -> Option<i64>) -> Result<(), RuntimeError> {
+        let x = self.stack.last_mut().unwrap();
+        *x = f(*x).ok_or(RuntimeError::Overflow)?;
+        Ok(())
+    }
+
+    fn binary(&mut self, f: impl Fn(i64, i64) -> Option<i64>) -> Result<(), RuntimeError> {
+        let y = self.stack.pop().unwrap();
+        let x = self.stack.last_mut().unwrap();
+        *x = f(*x, y).ok_or(RuntimeError::Overflow)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bytecode::compile;
+    use crate::error::RuntimeError;
+    use crate::evaluator::Evaluator;
+    use crate::lexer::Lexer;
+    use crate::object::Object;
+    use crate::parser::Parser;
+    use crate::vm::Vm;
+
+    fn run(input: &str) -> Result<Object, RuntimeError> {
+        Vm::new().run(&compile(&Parser::new(Lexer::new(input)).parse().unwrap()))
+    }
+
+    #[test]
+    fn test_run() {
+        let tests = vec![
+            ("5", Ok(Object::Int(5))),
+            ("1 + 2 * 3", Ok(Object::Int(7))),
+            ("(1 + 2) * 3", Ok(Object::Int(9))),
+            ("8 / -(3 - 2)", Ok(Object::Int(-8))),
+            ("~5 + 256", Ok(Object::Int(250))),
+            ("!0 - !7", Ok(Object::Int(1))),
+            ("7 / 2", Ok(Object::Int(3))),
+            ("-7 / 2", Ok(Object::Int(-3))),
+            ("9223372036854775807 + 1", Err(RuntimeError::Overflow)),
+            ("-(-9223372036854775808)", Err(RuntimeError::Overflow)),
+            ("-9223372036854775808 / -1", Err(RuntimeError::Overflow)),
+            ("-9223372036854775808 / 1", Ok(Object::Int(i64::MIN))),
+            ("1 / (2 - 2)", Err(RuntimeError::DivideByZero)),
+            ("", Err(RuntimeError::FoundNoProgram)),
+        ];
+
+        for (input, want) in tests {
+            assert_eq!(want, run(input));
+        }
+    }
+
+    #[test]
+    fn test_run_matches_evaluator() {
+        let tests = vec![
+            "10 + 1",
+            "2 * 3 + 4 * 5",
+            "-30 * 2 - (-60)",
+            "((1 + 2) * (3 + 4)) / (5 - 6)",
+            "!(3 - 1) * ~!0",
+            "100 / 7 / 2",
+            "5 / (1 - 1) + 1",
+            "4611686018427387904 * -2",
+            "4611686018427387904 * 2",
+            "(9223372036854775807 + 1) / 0",
+        ];
+
+        for input in tests {
+            let program = Parser::new(Lexer::new(input)).parse().unwrap();
+            assert_eq!(Evaluator::new().eval(program.clone()), run(input));
+        }
+    }
+
+    #[test]
+    fn test_run_reuses_vm() {
+        let chunk = compile(&Parser::new(Lexer::new("6 * 7")).parse().unwrap());
+        let mut vm = Vm::new();
+
+        for _ in 0..3 {
+            assert_eq!(Ok(Object::Int(42)), vm.run(&chunk));
+        }
+    }
+}
@@ -0,0 +1,670 @@
+//! Runs every program in `tests/cases` with the evaluator and every backend.
+//!
+//! A case is a `.calc` file with a `.expect` file next to it saying what
+//! the program comes to, one `key: value` line each:
+//!
+//! - `value: V`: it prints V.
+//! - `error: MESSAGE`: it stops with the evaluator's runtime error.
+//! - `wrapped: V`: backends that wrap around on overflow print V instead.
+//! - `exit: N`: built with `--exit-code`, it exits with status N.
+//!
+//! Lines starting with `#` are comments.
+//!
+//! Backends whose tools are not installed are skipped.
+
+use calculator::compiler::TRAP_STATUS;
+use calculator::error::RuntimeError;
+use calculator::evaluator::Evaluator;
+use calculator::lexer::Lexer;
+use calculator::object::Object;
+use calculator::parser::Parser;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const CALCULATOR: &str = env!("CARGO_BIN_EXE_calculator");
+
+/// The status the calculator itself exits with on a runtime error.
+const CLI_ERROR: i32 = 252;
+
+/// The status compiled programs exit with on a runtime error.
+const PROGRAM_ERROR: i32 = TRAP_STATUS as i32;
+
+#[derive(Debug)]
+struct Case {
+    name: String,
+    path: PathBuf,
+    source: String,
+    expect: Result<i64, RuntimeError>,
+    wrapped: Option<i64>,
+    exit: Option<i32>,
+}
+
+impl Case {
+    fn load(path: &Path) -> Self {
+        let name = path.file_stem().unwrap().to_string_lossy().to_string();
+        let source = std::fs::read_to_string(path).unwrap();
+        let expectations = std::fs::read_to_string(path.with_extension("expect"))
+            .unwrap_or_else(|_| panic!("{}: found no .expect file", name));
+
+        let mut expect = None;
+        let mut wrapped = None;
+        let mut exit = None;
+        for line in expectations.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => panic!("{}: {} is not a key: value line", name, line),
+            };
+            let number = |value: &str| -> i64 {
+                value
+                    .parse()
+                    .unwrap_or_else(|_| panic!("{}: {} is not a number", name, value))
+            };
+            match key {
+                "value" => expect = Some(Ok(number(value))),
+                "error" => expect = Some(Err(error(&name, value))),
+                "wrapped" => wrapped = Some(number(value)),
+                "exit" => exit = Some(number(value) as i32),
+                _ => panic!("{}: {} is not an expectation", name, key),
+            }
+        }
+
+        Case {
+            expect: expect.unwrap_or_else(|| panic!("{}: found no value or error", name)),
+            name,
+            path: path.to_path_buf(),
+            source,
+            wrapped,
+            exit,
+        }
+    }
+
+    /// What a backend handling errors this way should come to, or None if
+    /// the case does not say.
+    fn outcome(&self, division: Handling, overflow: Handling) -> Option<Outcome> {
+        let e = match &self.expect {
+            Ok(i) => return Some(Outcome::Value(*i)),
+            Err(e) => e,
+        };
+        let handling = match e {
+            RuntimeError::DivideByZero => division,
+            _ => overflow,
+        };
+
+        match handling {
+            Handling::Reports => Some(Outcome::Error(e.clone())),
+            Handling::Traps => Some(Outcome::Trap),
+            Handling::Wraps => self.wrapped.map(Outcome::Value),
+            Handling::Undefined => None,
+        }
+    }
+}
+
+fn error(name: &str, message: &str) -> RuntimeError {
+    runtime_error(message).unwrap_or_else(|| panic!("{}: {} is not an error", name, message))
+}
+
+fn runtime_error(message: &str) -> Option<RuntimeError> {
+    let errors = [RuntimeError::DivideByZero, RuntimeError::Overflow];
+    errors.iter().find(|e| e.to_string() == message).cloned()
+}
+
+fn cases() -> Vec<Case> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/cases");
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension() == Some("calc".as_ref()))
+        .collect();
+    paths.sort();
+    paths.iter().map(|path| Case::load(path)).collect()
+}
+
+/// How a backend deals with one kind of runtime error.
+#[derive(Clone, Copy, Debug)]
+enum Handling {
+    /// Reports it with the evaluator's message.
+    Reports,
+    /// Stops without a message, e.g. by a signal.
+    Traps,
+    /// Wraps around, giving the case's `wrapped` value.
+    Wraps,
+    /// Gives nothing a case can state.
+    Undefined,
+}
+
+#[derive(PartialEq, Debug)]
+enum Outcome {
+    Value(i64),
+    Error(RuntimeError),
+    Trap,
+}
+
+/// Reads what a program came to. It has to report runtime errors with
+/// `error_status`.
+fn outcome(output: &Output, error_status: i32) -> Outcome {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    match output.status.code() {
+        Some(0) => match stdout.trim().parse() {
+            Ok(i) => Outcome::Value(i),
+            Err(_) => Outcome::Trap,
+        },
+        Some(status) if status == error_status => {
+            let message = format!("{}{}", stdout, stderr);
+            match runtime_error(message.trim()) {
+                Some(e) => Outcome::Error(e),
+                None => Outcome::Trap,
+            }
+        }
+        _ => Outcome::Trap,
+    }
+}
+
+/// Runs every case that says what a backend comes to, and fails with all
+/// the mismatches at once. `run` gets a scratch path to build files at.
+fn run_cases<F>(backend: &str, division: Handling, overflow: Handling, mut run: F)
+where
+    F: FnMut(&Case, &Path) -> Result<Outcome, String>,
+{
+    let mut failures = vec![];
+    for case in cases() {
+        let want = match case.outcome(division, overflow) {
+            Some(want) => want,
+            None => continue,
+        };
+
+        let scratch = Scratch::new();
+        match run(&case, &scratch.0) {
+            Ok(got) if got == want => {}
+            Ok(got) => failures.push(format!(
+                "[{}][{}] want {:?}, got {:?}",
+                backend, case.name, want, got
+            )),
+            Err(e) => failures.push(format!("[{}][{}] {}", backend, case.name, e)),
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+static SCRATCH: AtomicUsize = AtomicUsize::new(0);
+
+/// A unique path in the temporary directory. Files at it, with any of the
+/// extensions the backends use, are removed on drop.
+struct Scratch(PathBuf);
+
+impl Scratch {
+    fn new() -> Self {
+        Scratch(std::env::temp_dir().join(format!(
+            "calculator-case-{}-{}",
+            std::process::id(),
+            SCRATCH.fetch_add(1, Ordering::SeqCst)
+        )))
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+        for extension in &["s", "c", "o", "h", "ll", "cbc", "wasm"] {
+            let _ = std::fs::remove_file(self.0.with_extension(extension));
+        }
+    }
+}
+
+/// Names a backend after how the calculator is run for it.
+fn name(backend: &str, flags: &[&str]) -> String {
+    [&[backend][..], flags].concat().join(" ")
+}
+
+/// Runs the calculator on a case's file.
+fn calculator(case: &Case, arguments: &[&str]) -> Output {
+    Command::new(CALCULATOR)
+        .arg("-f")
+        .arg(&case.path)
+        .args(arguments)
+        .output()
+        .unwrap()
+}
+
+/// Runs a step that has to succeed before the program can run.
+fn build(command: &mut Command) -> Result<Output, String> {
+    let output = command
+        .output()
+        .map_err(|e| format!("{:?}: {}", command, e))?;
+    match output.status.success() {
+        true => Ok(output),
+        false => Err(format!(
+            "{:?} failed: {}{}",
+            command,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        )),
+    }
+}
+
+/// Writes what the calculator prints for a case to `path`.
+fn emit(case: &Case, arguments: &[&str], path: &Path) -> Result<(), String> {
+    let output = build(
+        Command::new(CALCULATOR)
+            .arg("-f")
+            .arg(&case.path)
+            .args(arguments),
+    )?;
+    std::fs::write(path, output.stdout).map_err(|e| e.to_string())
+}
+
+fn run(path: &Path) -> Output {
+    Command::new(path).output().unwrap()
+}
+
+fn has(tool: &str) -> bool {
+    let found = Command::new(tool)
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok();
+    if !found {
+        println!("[Skip] {} not found", tool);
+    }
+    found
+}
+
+#[test]
+fn evaluator() {
+    run_cases(
+        "evaluator",
+        Handling::Reports,
+        Handling::Reports,
+        |case, _| {
+            let program = Parser::new(Lexer::new(&case.source))
+                .parse()
+                .map_err(|e| format!("{:?}", e))?;
+            Ok(match Evaluator::new().eval(program) {
+                Ok(Object::Int(i)) => Outcome::Value(i),
+                Err(e) => Outcome::Error(e),
+            })
+        },
+    );
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn asm() {
+    if !has("gcc") {
+        return;
+    }
+
+    let checked: &[&[&str]] = &[
+        &["--checked"],
+        &["--checked", "--registers"],
+        &["--checked", "--registers", "-O"],
+        &["--checked", "--syntax", "att"],
+        &["--checked", "--syntax", "att", "--registers", "-O"],
+    ];
+    let unchecked: &[&[&str]] = &[&[], &["--registers", "-O"]];
+
+    for (configs, overflow) in &[
+        (checked, Handling::Reports),
+        (unchecked, Handling::Undefined),
+    ] {
+        for flags in configs.iter() {
+            let backend = name("asm", flags);
+            run_cases(&backend, Handling::Reports, *overflow, |case, scratch| {
+                let source = scratch.with_extension("s");
+                emit(case, flags, &source)?;
+                build(Command::new("gcc").arg("-o").arg(scratch).arg(&source))?;
+                Ok(outcome(&run(scratch), PROGRAM_ERROR))
+            });
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn exe() {
+    let configs: &[(&[&str], Handling)] = &[
+        (&["--checked"], Handling::Reports),
+        (&["--checked", "--registers", "-O"], Handling::Reports),
+        (&[], Handling::Undefined),
+    ];
+
+    for (flags, overflow) in configs {
+        let backend = name("exe", flags);
+        run_cases(&backend, Handling::Reports, *overflow, |case, scratch| {
+            let mut arguments = vec!["--emit", "exe", "-o", scratch.to_str().unwrap()];
+            arguments.extend(flags.iter());
+            build(
+                Command::new(CALCULATOR)
+                    .arg("-f")
+                    .arg(&case.path)
+                    .args(&arguments),
+            )?;
+            Ok(outcome(&run(scratch), PROGRAM_ERROR))
+        });
+    }
+}
+
+/// Without `--checked`, x86-64 code still stops on `i64::MIN / -1`, which
+/// `idiv` cannot compute.
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn unchecked_division() {
+    let case = cases()
+        .into_iter()
+        .find(|case| case.name == "overflow_div")
+        .unwrap();
+    let want = Outcome::Error(RuntimeError::Overflow);
+
+    let scratch = Scratch::new();
+    build(
+        Command::new(CALCULATOR)
+            .arg("-f")
+            .arg(&case.path)
+            .args(["--emit", "exe", "-o"])
+            .arg(&scratch.0),
+    )
+    .unwrap();
+    assert_eq!(want, outcome(&run(&scratch.0), PROGRAM_ERROR), "exe");
+
+    if has("gcc") {
+        let scratch = Scratch::new();
+        let source = scratch.0.with_extension("s");
+        emit(&case, &[], &source).unwrap();
+        build(Command::new("gcc").arg("-o").arg(&scratch.0).arg(&source)).unwrap();
+        assert_eq!(want, outcome(&run(&scratch.0), PROGRAM_ERROR), "asm");
+    }
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn jit() {
+    for flags in &[&[][..], &["--registers"], &["--registers", "-O"]] {
+        let backend = name("jit", flags);
+        run_cases(&backend, Handling::Reports, Handling::Reports, |case, _| {
+            let mut arguments = vec!["--jit"];
+            arguments.extend(flags.iter());
+            Ok(outcome(&calculator(case, &arguments), CLI_ERROR))
+        });
+    }
+}
+
+#[test]
+fn bytecode() {
+    run_cases(
+        "bytecode",
+        Handling::Reports,
+        Handling::Reports,
+        |case, scratch| {
+            let chunk = scratch.with_extension("cbc");
+            build(
+                Command::new(CALCULATOR)
+                    .args(["compile", "-f"])
+                    .arg(&case.path)
+                    .arg("-o")
+                    .arg(&chunk),
+            )?;
+            let output = Command::new(CALCULATOR)
+                .arg("run")
+                .arg(&chunk)
+                .output()
+                .unwrap();
+            Ok(outcome(&output, CLI_ERROR))
+        },
+    );
+}
+
+#[test]
+fn c() {
+    if !has("cc") {
+        return;
+    }
+
+    run_cases(
+        "c",
+        Handling::Reports,
+        Handling::Reports,
+        |case, scratch| {
+            let source = scratch.with_extension("c");
+            emit(case, &["--emit", "c"], &source)?;
+            build(Command::new("cc").arg("-o").arg(scratch).arg(&source))?;
+            Ok(outcome(&run(scratch), PROGRAM_ERROR))
+        },
+    );
+}
+
+#[test]
+fn llvm() {
+    if !has("lli") {
+        return;
+    }
+
+    run_cases("llvm", Handling::Traps, Handling::Traps, |case, scratch| {
+        let source = scratch.with_extension("ll");
+        emit(case, &["--emit", "llvm"], &source)?;
+        let output = Command::new("lli").arg(&source).output().unwrap();
+        Ok(outcome(&output, PROGRAM_ERROR))
+    });
+}
+
+#[test]
+fn wasm() {
+    if !has("node") {
+        return;
+    }
+
+    // i64.div_s traps on i64::MIN / -1, while the other operations wrap.
+    run_cases(
+        "wasm",
+        Handling::Traps,
+        Handling::Undefined,
+        |case, scratch| {
+            let module = scratch.with_extension("wasm");
+            emit(case, &["--emit", "wasm"], &module)?;
+            let script = "const bytes = require('fs').readFileSync(process.argv[1]);
+            WebAssembly.instantiate(bytes)
+                .then(m => console.log(m.instance.exports.main().toString()));";
+            let output = Command::new("node")
+                .args(["-e", script])
+                .arg(&module)
+                .output()
+                .unwrap();
+            Ok(outcome(&output, PROGRAM_ERROR))
+        },
+    );
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn obj() {
+    if !has("cc") {
+        return;
+    }
+
+    // Object files return runtime errors, which the caller reports.
+    let main = |header: &Path| {
+        format!(
+            r#"#include <inttypes.h>
+#include <stdio.h>
+#include "{}"
+
+int main(void) {{
+    struct calc_formula_result r = calc_formula();
+    switch (r.error) {{
+    case 0:
+        printf("%" PRId64 "\n", r.value);
+        return 0;
+    case CALC_FORMULA_DIVIDE_BY_ZERO:
+        fputs("{}\n", stderr);
+        return {};
+    case CALC_FORMULA_OVERFLOW:
+        fputs("{}\n", stderr);
+        return {};
+    }}
+    return -1;
+}}
+"#,
+            header.display(),
+            RuntimeError::DivideByZero,
+            PROGRAM_ERROR,
+            RuntimeError::Overflow,
+            PROGRAM_ERROR
+        )
+    };
+
+    for (flags, overflow) in [
+        (&["--checked"][..], Handling::Reports),
+        (&["--checked", "--registers", "-O"][..], Handling::Reports),
+        (&[][..], Handling::Undefined),
+    ] {
+        run_cases(
+            &name("obj", flags),
+            Handling::Reports,
+            overflow,
+            |case, scratch| {
+                let (object, header, source) = (
+                    scratch.with_extension("o"),
+                    scratch.with_extension("h"),
+                    scratch.with_extension("c"),
+                );
+                build(
+                    Command::new(CALCULATOR)
+                        .arg("-f")
+                        .arg(&case.path)
+                        .args(["--emit", "obj", "--symbol", "calc_formula"])
+                        .args(flags)
+                        .arg("-o")
+                        .arg(&object)
+                        .arg("--header")
+                        .arg(&header),
+                )?;
+                std::fs::write(&source, main(&header)).unwrap();
+                build(
+                    Command::new("cc")
+                        .arg("-o")
+                        .arg(scratch)
+                        .arg(&source)
+                        .arg(&object),
+                )?;
+                Ok(outcome(&run(scratch), PROGRAM_ERROR))
+            },
+        );
+    }
+}
+
+/// Cross-compiles with `cc` and runs the program under `qemu`.
+fn cross(target: &str, cc: &str, qemu: &str) {
+    if !has(cc) || !has(qemu) {
+        return;
+    }
+
+    // Division by zero gives a number rather than a trap on these machines.
+    run_cases(
+        target,
+        Handling::Undefined,
+        Handling::Wraps,
+        |case, scratch| {
+            let source = scratch.with_extension("s");
+            emit(case, &["--target", target], &source)?;
+            build(
+                Command::new(cc)
+                    .arg("-static")
+                    .arg("-o")
+                    .arg(scratch)
+                    .arg(&source),
+            )?;
+            let output = Command::new(qemu).arg(scratch).output().unwrap();
+            Ok(outcome(&output, PROGRAM_ERROR))
+        },
+    );
+}
+
+#[test]
+fn aarch64() {
+    cross("aarch64-linux", "aarch64-linux-gnu-gcc", "qemu-aarch64");
+}
+
+#[test]
+fn riscv64() {
+    cross("riscv64", "riscv64-linux-gnu-gcc", "qemu-riscv64");
+}
+
+/// Programs built with `--exit-code`, which keeps only the lowest byte of
+/// the result. The program is read from stdin here, which takes a single
+/// line.
+#[test]
+fn exit_code() {
+    let mut failures = vec![];
+    for case in cases() {
+        let want = match case.exit {
+            Some(want) => want,
+            None => continue,
+        };
+
+        let mut builds = vec![];
+        if cfg!(all(target_os = "linux", target_arch = "x86_64")) {
+            builds.push("exe");
+            if has("gcc") {
+                builds.push("asm");
+            }
+        }
+        if has("lli") {
+            builds.push("llvm");
+        }
+
+        for kind in builds {
+            let scratch = Scratch::new();
+            let mut command = Command::new(CALCULATOR);
+            command
+                .args(["--exit-code", "--emit", kind])
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped());
+            if kind == "exe" {
+                command.arg("-o").arg(&scratch.0);
+            }
+
+            let mut child = command.spawn().unwrap();
+            child
+                .stdin
+                .take()
+                .unwrap()
+                .write_all(case.source.as_bytes())
+                .unwrap();
+            let output = child.wait_with_output().unwrap();
+            assert!(output.status.success(), "{}: {:?}", case.name, output);
+
+            if kind == "asm" {
+                let source = scratch.0.with_extension("s");
+                std::fs::write(&source, &output.stdout).unwrap();
+                build(Command::new("gcc").arg("-o").arg(&scratch.0).arg(&source)).unwrap();
+            }
+
+            let got = match kind {
+                "llvm" => {
+                    let source = scratch.0.with_extension("ll");
+                    std::fs::write(&source, output.stdout).unwrap();
+                    Command::new("lli").arg(&source).output().unwrap()
+                }
+                _ => run(&scratch.0),
+            }
+            .status
+            .code();
+            if got != Some(want) {
+                failures.push(format!(
+                    "[exit-code {}][{}] want {}, got {:?}",
+                    kind, case.name, want, got
+                ));
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
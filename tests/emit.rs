@@ -0,0 +1,71 @@
+//! Checks that emitting several outputs in one run writes the same files as
+//! emitting them one at a time.
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+const CALCULATOR: &str = env!("CARGO_BIN_EXE_calculator");
+
+/// Outputs written as text, with the extension each one gets.
+const TEXT: &[(&str, &str)] = &[
+    ("tokens", "tokens"),
+    ("ast", "ast"),
+    ("asm", "s"),
+    ("ir", "ir"),
+    ("wat", "wat"),
+    ("c", "c"),
+    ("llvm", "ll"),
+];
+
+fn calculator(input: &Path, arguments: &[&str]) -> Output {
+    let output = Command::new(CALCULATOR)
+        .arg("-f")
+        .arg(input)
+        .args(arguments)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}: {:?}", arguments, output);
+    output
+}
+
+#[test]
+fn multiple() {
+    let dir = std::env::temp_dir().join(format!("calculator-emit-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("program.calc");
+    std::fs::write(&input, "(1+2)*-3\n").unwrap();
+
+    let emits: Vec<&str> = TEXT.iter().map(|(emit, _)| *emit).collect();
+    calculator(&input, &["--emit", &emits.join(","), "--emit", "wasm"]);
+    for (emit, extension) in TEXT {
+        let got = std::fs::read(dir.join("program").with_extension(extension)).unwrap();
+        let want = calculator(&input, &["--emit", emit]).stdout;
+        assert_eq!(want, got, "--emit {}", emit);
+    }
+    let want = calculator(&input, &["--emit", "wasm"]).stdout;
+    assert_eq!(want, std::fs::read(dir.join("program.wasm")).unwrap());
+
+    // With -o, files are named after it, and machine code goes to the same
+    // places as when emitted alone.
+    let base = dir.join("out");
+    let base = base.to_str().unwrap();
+    calculator(
+        &input,
+        &["--emit", "asm,exe,obj", "--symbol", "main", "-o", base],
+    );
+    let alone = dir.join("alone");
+    let alone = alone.to_str().unwrap();
+    calculator(&input, &["--emit", "exe", "-o", alone]);
+    assert_eq!(std::fs::read(alone).unwrap(), std::fs::read(base).unwrap());
+    calculator(&input, &["--emit", "obj", "--symbol", "main", "-o", alone]);
+    assert_eq!(
+        std::fs::read(alone).unwrap(),
+        std::fs::read(format!("{}.o", base)).unwrap()
+    );
+    assert_eq!(
+        calculator(&input, &["--emit", "asm"]).stdout,
+        std::fs::read(format!("{}.s", base)).unwrap()
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
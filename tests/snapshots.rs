@@ -0,0 +1,105 @@
+//! Compares what every stage makes of the programs in `tests/snapshots`
+//! with the files stored next to them, one directory per program.
+//!
+//! Run `BLESS=1 cargo test --test snapshots` to write the current output
+//! instead, and review the diff.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const CALCULATOR: &str = env!("CARGO_BIN_EXE_calculator");
+
+/// Snapshot file names and the arguments that print them.
+const EMITS: &[(&str, &[&str])] = &[
+    ("tokens.txt", &["--emit", "tokens"]),
+    ("ast.txt", &["--emit", "ast"]),
+    ("ir.txt", &["--emit", "ir"]),
+    ("x86_64.s", &["--emit", "asm"]),
+    ("x86_64-registers.s", &["--registers"]),
+    ("x86_64-att.s", &["--syntax", "att"]),
+    ("x86_64-checked.s", &["--checked", "--registers"]),
+    ("aarch64.s", &["--target", "aarch64-linux"]),
+    ("riscv64.s", &["--target", "riscv64"]),
+    ("c.c", &["--emit", "c"]),
+    ("llvm.ll", &["--emit", "llvm"]),
+    ("wat.wat", &["--emit", "wat"]),
+];
+
+fn emit(input: &Path, arguments: &[&str]) -> String {
+    let output = Command::new(CALCULATOR)
+        .arg("-f")
+        .arg(input)
+        .args(arguments)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}: {:?}", arguments, output);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn inputs() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots");
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension() == Some("calc".as_ref()))
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// The first line where `want` and `got` differ, for the failure message.
+fn first_difference(want: &str, got: &str) -> String {
+    let (mut want_lines, mut got_lines) = (want.lines(), got.lines());
+    for n in 1.. {
+        match (want_lines.next(), got_lines.next()) {
+            (None, None) => break,
+            (w, g) if w == g => continue,
+            (w, g) => {
+                return format!(
+                    "line {}:\n  want: {}\n  got:  {}",
+                    n,
+                    w.unwrap_or("<end>"),
+                    g.unwrap_or("<end>")
+                )
+            }
+        }
+    }
+    "only in trailing newlines".to_string()
+}
+
+#[test]
+fn snapshots() {
+    let bless = std::env::var_os("BLESS").is_some();
+
+    let mut failures = vec![];
+    for input in inputs() {
+        let dir = input.with_extension("");
+
+        for (name, arguments) in EMITS {
+            let got = emit(&input, arguments);
+            let path = dir.join(name);
+            let shown = path.strip_prefix(env!("CARGO_MANIFEST_DIR")).unwrap();
+            if bless {
+                std::fs::create_dir_all(&dir).unwrap();
+                std::fs::write(&path, got).unwrap();
+                continue;
+            }
+
+            match std::fs::read_to_string(&path) {
+                Ok(want) if want == got => {}
+                Ok(want) => failures.push(format!(
+                    "{} differs at {}",
+                    shown.display(),
+                    first_difference(&want, &got)
+                )),
+                Err(_) => failures.push(format!("{} is missing", shown.display())),
+            }
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "\n{}\n\nRun BLESS=1 cargo test --test snapshots to update them.",
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,25 @@
+.global main
+main:
+  stp x29, x30, [sp, #-16]!
+  mov x29, sp
+  bl calc_expr
+  mov x1, x0
+  adrp x0, .Lformat
+  add x0, x0, :lo12:.Lformat
+  bl printf
+  mov x0, #0
+  ldp x29, x30, [sp], #16
+  ret
+.section .rodata
+.Lformat:
+  .asciz "%ld\n"
+.text
+.global calc_expr
+calc_expr:
+  mov x9, #7
+  mov x10, #2
+  sub x9, x9, x10
+  mov x10, #100
+  sdiv x9, x10, x9
+  mov x0, x9
+  ret
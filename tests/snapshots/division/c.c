@@ -0,0 +1,41 @@
+#include <inttypes.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+static void calc_overflow(void) {
+    fputs("Found an integer overflow.\n", stderr);
+    exit(EXIT_FAILURE);
+}
+
+static int64_t calc_sub(int64_t a, int64_t b) {
+    if (b < 0 ? a > INT64_MAX + b : a < INT64_MIN + b) {
+        calc_overflow();
+    }
+    return a - b;
+}
+
+static int64_t calc_div(int64_t a, int64_t b) {
+    if (b == 0) {
+        fputs("Divide a number by 0.\n", stderr);
+        exit(EXIT_FAILURE);
+    }
+    if (a == INT64_MIN && b == -1) {
+        calc_overflow();
+    }
+    return a / b;
+}
+
+int64_t main_expr(void) {
+    const int64_t v0 = INT64_C(7);
+    const int64_t v1 = INT64_C(2);
+    const int64_t v2 = calc_sub(v0, v1);
+    const int64_t v3 = INT64_C(100);
+    const int64_t v4 = calc_div(v3, v2);
+    return v4;
+}
+
+int main(void) {
+    printf("%" PRId64 "\n", main_expr());
+    return 0;
+}
@@ -0,0 +1,40 @@
+declare { i64, i1 } @llvm.sadd.with.overflow.i64(i64, i64)
+declare { i64, i1 } @llvm.ssub.with.overflow.i64(i64, i64)
+declare { i64, i1 } @llvm.smul.with.overflow.i64(i64, i64)
+declare void @llvm.trap()
+
+define i64 @calc_expr() {
+entry:
+  %v2.result = call { i64, i1 } @llvm.ssub.with.overflow.i64(i64 7, i64 2)
+  %v2 = extractvalue { i64, i1 } %v2.result, 0
+  %v2.overflow = extractvalue { i64, i1 } %v2.result, 1
+  br i1 %v2.overflow, label %trap, label %v2.ok
+
+v2.ok:
+  %v4.zero = icmp eq i64 %v2, 0
+  %v4.min = icmp eq i64 100, -9223372036854775808
+  %v4.minus = icmp eq i64 %v2, -1
+  %v4.overflow = and i1 %v4.min, %v4.minus
+  %v4.invalid = or i1 %v4.zero, %v4.overflow
+  br i1 %v4.invalid, label %trap, label %v4.ok
+
+v4.ok:
+  %v4 = sdiv i64 100, %v2
+  ret i64 %v4
+
+trap:
+  call void @llvm.trap()
+  unreachable
+}
+
+@format = private unnamed_addr constant [5 x i8] c"%ld\0A\00"
+
+declare i32 @printf(i8*, ...)
+
+define i32 @main() {
+entry:
+  %value = call i64 @calc_expr()
+  %format = getelementptr [5 x i8], [5 x i8]* @format, i64 0, i64 0
+  call i32 (i8*, ...) @printf(i8* %format, i64 %value)
+  ret i32 0
+}
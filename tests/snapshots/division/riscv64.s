@@ -0,0 +1,30 @@
+.global main
+main:
+  addi sp, sp, -16
+  sd ra, 8(sp)
+  call calc_expr
+  mv a1, a0
+  lla a0, .Lformat
+  call printf
+  li a0, 0
+  ld ra, 8(sp)
+  addi sp, sp, 16
+  ret
+.section .rodata
+.Lformat:
+  .asciz "%ld\n"
+.text
+.global calc_expr
+calc_expr:
+  addi sp, sp, -16
+  sd s0, 8(sp)
+  addi s0, sp, 16
+  li t0, 7
+  li t1, 2
+  sub t0, t0, t1
+  li t1, 100
+  div t0, t1, t0
+  mv a0, t0
+  ld s0, 8(sp)
+  addi sp, sp, 16
+  ret
@@ -0,0 +1,10 @@
+(module
+  (func (export "main") (result i64)
+    (local i64)
+    i64.const 7
+    i64.const 2
+    i64.sub
+    local.set 0
+    i64.const 100
+    local.get 0
+    i64.div_s))
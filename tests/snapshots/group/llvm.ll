@@ -0,0 +1,49 @@
+declare { i64, i1 } @llvm.sadd.with.overflow.i64(i64, i64)
+declare { i64, i1 } @llvm.ssub.with.overflow.i64(i64, i64)
+declare { i64, i1 } @llvm.smul.with.overflow.i64(i64, i64)
+declare void @llvm.trap()
+
+define i64 @calc_expr() {
+entry:
+  %v2.result = call { i64, i1 } @llvm.sadd.with.overflow.i64(i64 1, i64 2)
+  %v2 = extractvalue { i64, i1 } %v2.result, 0
+  %v2.overflow = extractvalue { i64, i1 } %v2.result, 1
+  br i1 %v2.overflow, label %trap, label %v2.ok
+
+v2.ok:
+  %v5.result = call { i64, i1 } @llvm.ssub.with.overflow.i64(i64 3, i64 4)
+  %v5 = extractvalue { i64, i1 } %v5.result, 0
+  %v5.overflow = extractvalue { i64, i1 } %v5.result, 1
+  br i1 %v5.overflow, label %trap, label %v5.ok
+
+v5.ok:
+  %v6.result = call { i64, i1 } @llvm.ssub.with.overflow.i64(i64 0, i64 %v5)
+  %v6 = extractvalue { i64, i1 } %v6.result, 0
+  %v6.overflow = extractvalue { i64, i1 } %v6.result, 1
+  br i1 %v6.overflow, label %trap, label %v6.ok
+
+v6.ok:
+  %v7.result = call { i64, i1 } @llvm.smul.with.overflow.i64(i64 %v2, i64 %v6)
+  %v7 = extractvalue { i64, i1 } %v7.result, 0
+  %v7.overflow = extractvalue { i64, i1 } %v7.result, 1
+  br i1 %v7.overflow, label %trap, label %v7.ok
+
+v7.ok:
+  ret i64 %v7
+
+trap:
+  call void @llvm.trap()
+  unreachable
+}
+
+@format = private unnamed_addr constant [5 x i8] c"%ld\0A\00"
+
+declare i32 @printf(i8*, ...)
+
+define i32 @main() {
+entry:
+  %value = call i64 @calc_expr()
+  %format = getelementptr [5 x i8], [5 x i8]* @format, i64 0, i64 0
+  call i32 (i8*, ...) @printf(i8* %format, i64 %value)
+  ret i32 0
+}
@@ -0,0 +1,11 @@
+(module
+  (func (export "main") (result i64)
+    i64.const 1
+    i64.const 2
+    i64.add
+    i64.const 3
+    i64.const 4
+    i64.sub
+    i64.const -1
+    i64.mul
+    i64.mul))
@@ -0,0 +1,90 @@
+.global main
+main:
+  callq calc_expr
+  subq $32, %rsp
+  leaq 32(%rsp), %rsi
+  subq $1, %rsi
+  movq $10, %rdx
+  movb %dl, (%rsi)
+  movq %rax, %r8
+  cmpq $0, %rax
+  jle print_digit
+  negq %rax
+print_digit:
+  movq $10, %rcx
+  cqto
+  idivq %rcx
+  movq $48, %rdi
+  subq %rdx, %rdi
+  subq $1, %rsi
+  movb %dil, (%rsi)
+  cmpq $0, %rax
+  jne print_digit
+  cmpq $0, %r8
+  jge print_write
+  movq $45, %rdi
+  subq $1, %rsi
+  movb %dil, (%rsi)
+print_write:
+  movq $1, %rax
+  movq $1, %rdi
+  leaq 32(%rsp), %rdx
+  subq %rsi, %rdx
+  syscall
+  addq $32, %rsp
+  movq $0, %rax
+  ret
+calc_expr:
+  pushq $1
+  pushq $2
+  popq %rdi
+  popq %rax
+  addq %rdi, %rax
+  pushq %rax
+  pushq $3
+  pushq $4
+  popq %rdi
+  popq %rax
+  subq %rdi, %rax
+  pushq %rax
+  popq %rax
+  negq %rax
+  pushq %rax
+  popq %rdi
+  popq %rax
+  imulq %rdi, %rax
+  pushq %rax
+  popq %rax
+  ret
+divide_by_zero:
+  movq $11193492207970, %rax
+  pushq %rax
+  movq $2338042629976321568, %rax
+  pushq %rax
+  movq $6998705302874253636, %rax
+  pushq %rax
+  movq $1, %rax
+  movq $2, %rdi
+  movq %rsp, %rsi
+  movq $22, %rdx
+  syscall
+  movq $60, %rax
+  movq $1, %rdi
+  syscall
+overflow:
+  movq $667255, %rax
+  pushq %rax
+  movq $8028904877209317152, %rax
+  pushq %rax
+  movq $8243108378414311712, %rax
+  pushq %rax
+  movq $7953674032635014982, %rax
+  pushq %rax
+  movq $1, %rax
+  movq $2, %rdi
+  movq %rsp, %rsi
+  movq $27, %rdx
+  syscall
+  movq $60, %rax
+  movq $1, %rdi
+  syscall
@@ -0,0 +1,84 @@
+.intel_syntax noprefix
+.global main
+main:
+  call calc_expr
+  sub rsp, 32
+  lea rsi, [rsp+32]
+  sub rsi, 1
+  mov rdx, 10
+  mov byte ptr [rsi], dl
+  mov r8, rax
+  cmp rax, 0
+  jle print_digit
+  neg rax
+print_digit:
+  mov rcx, 10
+  cqo
+  idiv rcx
+  mov rdi, 48
+  sub rdi, rdx
+  sub rsi, 1
+  mov byte ptr [rsi], dil
+  cmp rax, 0
+  jne print_digit
+  cmp r8, 0
+  jge print_write
+  mov rdi, 45
+  sub rsi, 1
+  mov byte ptr [rsi], dil
+print_write:
+  mov rax, 1
+  mov rdi, 1
+  lea rdx, [rsp+32]
+  sub rdx, rsi
+  syscall
+  add rsp, 32
+  mov rax, 0
+  ret
+calc_expr:
+  mov rcx, 1
+  mov rsi, 2
+  add rcx, rsi
+  jo overflow
+  mov rsi, 3
+  mov rdi, 4
+  sub rsi, rdi
+  jo overflow
+  neg rsi
+  jo overflow
+  imul rcx, rsi
+  jo overflow
+  mov rax, rcx
+  ret
+divide_by_zero:
+  mov rax, 11193492207970
+  push rax
+  mov rax, 2338042629976321568
+  push rax
+  mov rax, 6998705302874253636
+  push rax
+  mov rax, 1
+  mov rdi, 2
+  mov rsi, rsp
+  mov rdx, 22
+  syscall
+  mov rax, 60
+  mov rdi, 1
+  syscall
+overflow:
+  mov rax, 667255
+  push rax
+  mov rax, 8028904877209317152
+  push rax
+  mov rax, 8243108378414311712
+  push rax
+  mov rax, 7953674032635014982
+  push rax
+  mov rax, 1
+  mov rdi, 2
+  mov rsi, rsp
+  mov rdx, 27
+  syscall
+  mov rax, 60
+  mov rdi, 1
+  syscall
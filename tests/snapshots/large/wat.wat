@@ -0,0 +1,7 @@
+(module
+  (func (export "main") (result i64)
+    i64.const 100000
+    i64.const 100000
+    i64.mul
+    i64.const 9999999999
+    i64.sub))
@@ -0,0 +1,21 @@
+.global main
+main:
+  stp x29, x30, [sp, #-16]!
+  mov x29, sp
+  bl calc_expr
+  mov x1, x0
+  adrp x0, .Lformat
+  add x0, x0, :lo12:.Lformat
+  bl printf
+  mov x0, #0
+  ldp x29, x30, [sp], #16
+  ret
+.section .rodata
+.Lformat:
+  .asciz "%ld\n"
+.text
+.global calc_expr
+calc_expr:
+  mov x9, #42
+  mov x0, x9
+  ret
@@ -0,0 +1,14 @@
+#include <inttypes.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+int64_t main_expr(void) {
+    const int64_t v0 = INT64_C(42);
+    return v0;
+}
+
+int main(void) {
+    printf("%" PRId64 "\n", main_expr());
+    return 0;
+}
@@ -0,0 +1,3 @@
+(module
+  (func (export "main") (result i64)
+    i64.const 42))
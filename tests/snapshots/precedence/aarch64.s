@@ -0,0 +1,28 @@
+.global main
+main:
+  stp x29, x30, [sp, #-16]!
+  mov x29, sp
+  bl calc_expr
+  mov x1, x0
+  adrp x0, .Lformat
+  add x0, x0, :lo12:.Lformat
+  bl printf
+  mov x0, #0
+  ldp x29, x30, [sp], #16
+  ret
+.section .rodata
+.Lformat:
+  .asciz "%ld\n"
+.text
+.global calc_expr
+calc_expr:
+  mov x9, #2
+  mov x10, #3
+  mov x11, #1
+  madd x9, x9, x10, x11
+  mov x10, #4
+  mov x11, #2
+  sdiv x10, x10, x11
+  sub x9, x9, x10
+  mov x0, x9
+  ret
@@ -0,0 +1,60 @@
+#include <inttypes.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+static void calc_overflow(void) {
+    fputs("Found an integer overflow.\n", stderr);
+    exit(EXIT_FAILURE);
+}
+
+static int64_t calc_add(int64_t a, int64_t b) {
+    if (b > 0 ? a > INT64_MAX - b : a < INT64_MIN - b) {
+        calc_overflow();
+    }
+    return a + b;
+}
+
+static int64_t calc_sub(int64_t a, int64_t b) {
+    if (b < 0 ? a > INT64_MAX + b : a < INT64_MIN + b) {
+        calc_overflow();
+    }
+    return a - b;
+}
+
+static int64_t calc_mul(int64_t a, int64_t b) {
+    if (a > 0 ? (b > 0 ? a > INT64_MAX / b : b < INT64_MIN / a)
+              : (b > 0 ? a < INT64_MIN / b : a != 0 && b < INT64_MAX / a)) {
+        calc_overflow();
+    }
+    return a * b;
+}
+
+static int64_t calc_div(int64_t a, int64_t b) {
+    if (b == 0) {
+        fputs("Divide a number by 0.\n", stderr);
+        exit(EXIT_FAILURE);
+    }
+    if (a == INT64_MIN && b == -1) {
+        calc_overflow();
+    }
+    return a / b;
+}
+
+int64_t main_expr(void) {
+    const int64_t v0 = INT64_C(2);
+    const int64_t v1 = INT64_C(3);
+    const int64_t v2 = calc_mul(v0, v1);
+    const int64_t v3 = INT64_C(1);
+    const int64_t v4 = calc_add(v3, v2);
+    const int64_t v5 = INT64_C(4);
+    const int64_t v6 = INT64_C(2);
+    const int64_t v7 = calc_div(v5, v6);
+    const int64_t v8 = calc_sub(v4, v7);
+    return v8;
+}
+
+int main(void) {
+    printf("%" PRId64 "\n", main_expr());
+    return 0;
+}
@@ -0,0 +1,52 @@
+declare { i64, i1 } @llvm.sadd.with.overflow.i64(i64, i64)
+declare { i64, i1 } @llvm.ssub.with.overflow.i64(i64, i64)
+declare { i64, i1 } @llvm.smul.with.overflow.i64(i64, i64)
+declare void @llvm.trap()
+
+define i64 @calc_expr() {
+entry:
+  %v2.result = call { i64, i1 } @llvm.smul.with.overflow.i64(i64 2, i64 3)
+  %v2 = extractvalue { i64, i1 } %v2.result, 0
+  %v2.overflow = extractvalue { i64, i1 } %v2.result, 1
+  br i1 %v2.overflow, label %trap, label %v2.ok
+
+v2.ok:
+  %v4.result = call { i64, i1 } @llvm.sadd.with.overflow.i64(i64 1, i64 %v2)
+  %v4 = extractvalue { i64, i1 } %v4.result, 0
+  %v4.overflow = extractvalue { i64, i1 } %v4.result, 1
+  br i1 %v4.overflow, label %trap, label %v4.ok
+
+v4.ok:
+  %v7.zero = icmp eq i64 2, 0
+  %v7.min = icmp eq i64 4, -9223372036854775808
+  %v7.minus = icmp eq i64 2, -1
+  %v7.overflow = and i1 %v7.min, %v7.minus
+  %v7.invalid = or i1 %v7.zero, %v7.overflow
+  br i1 %v7.invalid, label %trap, label %v7.ok
+
+v7.ok:
+  %v7 = sdiv i64 4, 2
+  %v8.result = call { i64, i1 } @llvm.ssub.with.overflow.i64(i64 %v4, i64 %v7)
+  %v8 = extractvalue { i64, i1 } %v8.result, 0
+  %v8.overflow = extractvalue { i64, i1 } %v8.result, 1
+  br i1 %v8.overflow, label %trap, label %v8.ok
+
+v8.ok:
+  ret i64 %v8
+
+trap:
+  call void @llvm.trap()
+  unreachable
+}
+
+@format = private unnamed_addr constant [5 x i8] c"%ld\0A\00"
+
+declare i32 @printf(i8*, ...)
+
+define i32 @main() {
+entry:
+  %value = call i64 @calc_expr()
+  %format = getelementptr [5 x i8], [5 x i8]* @format, i64 0, i64 0
+  call i32 (i8*, ...) @printf(i8* %format, i64 %value)
+  ret i32 0
+}
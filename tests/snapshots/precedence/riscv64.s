@@ -0,0 +1,34 @@
+.global main
+main:
+  addi sp, sp, -16
+  sd ra, 8(sp)
+  call calc_expr
+  mv a1, a0
+  lla a0, .Lformat
+  call printf
+  li a0, 0
+  ld ra, 8(sp)
+  addi sp, sp, 16
+  ret
+.section .rodata
+.Lformat:
+  .asciz "%ld\n"
+.text
+.global calc_expr
+calc_expr:
+  addi sp, sp, -16
+  sd s0, 8(sp)
+  addi s0, sp, 16
+  li t0, 2
+  li t1, 3
+  mul t0, t0, t1
+  li t1, 1
+  add t0, t1, t0
+  li t1, 4
+  li t2, 2
+  div t1, t1, t2
+  sub t0, t0, t1
+  mv a0, t0
+  ld s0, 8(sp)
+  addi sp, sp, 16
+  ret
@@ -0,0 +1,14 @@
+(module
+  (func (export "main") (result i64)
+    (local i64)
+    i64.const 2
+    i64.const 3
+    i64.mul
+    local.set 0
+    i64.const 1
+    local.get 0
+    i64.add
+    i64.const 4
+    i64.const 2
+    i64.div_s
+    i64.sub))
@@ -0,0 +1,29 @@
+#include <inttypes.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+static void calc_overflow(void) {
+    fputs("Found an integer overflow.\n", stderr);
+    exit(EXIT_FAILURE);
+}
+
+static int64_t calc_neg(int64_t a) {
+    if (a == INT64_MIN) {
+        calc_overflow();
+    }
+    return -a;
+}
+
+int64_t main_expr(void) {
+    const int64_t v0 = INT64_C(7);
+    const int64_t v1 = v0 == 0;
+    const int64_t v2 = ~v1;
+    const int64_t v3 = calc_neg(v2);
+    return v3;
+}
+
+int main(void) {
+    printf("%" PRId64 "\n", main_expr());
+    return 0;
+}
@@ -0,0 +1,34 @@
+declare { i64, i1 } @llvm.sadd.with.overflow.i64(i64, i64)
+declare { i64, i1 } @llvm.ssub.with.overflow.i64(i64, i64)
+declare { i64, i1 } @llvm.smul.with.overflow.i64(i64, i64)
+declare void @llvm.trap()
+
+define i64 @calc_expr() {
+entry:
+  %v1.zero = icmp eq i64 7, 0
+  %v1 = zext i1 %v1.zero to i64
+  %v2 = xor i64 %v1, -1
+  %v3.result = call { i64, i1 } @llvm.ssub.with.overflow.i64(i64 0, i64 %v2)
+  %v3 = extractvalue { i64, i1 } %v3.result, 0
+  %v3.overflow = extractvalue { i64, i1 } %v3.result, 1
+  br i1 %v3.overflow, label %trap, label %v3.ok
+
+v3.ok:
+  ret i64 %v3
+
+trap:
+  call void @llvm.trap()
+  unreachable
+}
+
+@format = private unnamed_addr constant [5 x i8] c"%ld\0A\00"
+
+declare i32 @printf(i8*, ...)
+
+define i32 @main() {
+entry:
+  %value = call i64 @calc_expr()
+  %format = getelementptr [5 x i8], [5 x i8]* @format, i64 0, i64 0
+  call i32 (i8*, ...) @printf(i8* %format, i64 %value)
+  ret i32 0
+}
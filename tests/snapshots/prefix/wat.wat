@@ -0,0 +1,9 @@
+(module
+  (func (export "main") (result i64)
+    i64.const 7
+    i64.eqz
+    i64.extend_i32_u
+    i64.const -1
+    i64.xor
+    i64.const -1
+    i64.mul))